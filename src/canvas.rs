@@ -0,0 +1,1208 @@
+use std::{path::Path, str::FromStr};
+
+use resvg::tiny_skia;
+use resvg::usvg::{self, fontdb, TreeParsing, TreeTextToPath};
+use unsvg::{Color, COLORS};
+
+use crate::display::{draw, place, Primitive};
+use crate::render::Renderer;
+
+/// Font used to rasterize labels, so png output doesn't depend on the fonts
+/// installed on the system.
+const BUNDLED_FONT: &[u8] = include_bytes!("../assets/DejaVuSans.ttf");
+const BUNDLED_FONT_FAMILY: &str = "DejaVu Sans";
+
+/// The most decimal places numbers are written with. An f32 doesn't hold
+/// more than this for canvas-sized coordinates, and larger factors overflow.
+pub const MAX_PRECISION: usize = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineCap {
+    Butt,
+    Round,
+    Square,
+}
+
+impl LineCap {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LineCap::Butt => "butt",
+            LineCap::Round => "round",
+            LineCap::Square => "square",
+        }
+    }
+
+    /// The line cap's number in PDF and PostScript.
+    pub fn postscript_code(&self) -> u8 {
+        match self {
+            LineCap::Butt => 0,
+            LineCap::Round => 1,
+            LineCap::Square => 2,
+        }
+    }
+}
+
+impl FromStr for LineCap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "BUTT" => Ok(LineCap::Butt),
+            "ROUND" => Ok(LineCap::Round),
+            "SQUARE" => Ok(LineCap::Square),
+            _ => Err(format!(
+                "Error: Unknown line cap '{s}'. Expected round, square or butt"
+            )),
+        }
+    }
+}
+
+/// How much of a stroke a gradient stretches over.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GradientSpan {
+    /// Every line starts in the pen color and ends in the gradient color.
+    Segment,
+    /// The color changes along each run of connected lines.
+    Path,
+}
+
+impl FromStr for GradientSpan {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "SEGMENT" => Ok(GradientSpan::Segment),
+            "PATH" => Ok(GradientSpan::Path),
+            _ => Err(format!(
+                "Error: Unknown gradient span '{s}'. Expected segment or path"
+            )),
+        }
+    }
+}
+
+impl GradientSpan {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GradientSpan::Segment => "segment",
+            GradientSpan::Path => "path",
+        }
+    }
+}
+
+/// A stroke that fades from the pen color to `to`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Gradient {
+    pub to: Color,
+    pub span: GradientSpan,
+}
+
+/// Everything about a stroke other than its pen color.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StrokeStyle {
+    pub width: f32,
+    pub line_cap: LineCap,
+    pub dash: Vec<f32>,
+    /// From 0 (invisible) to 1 (opaque).
+    pub opacity: f32,
+    pub gradient: Option<Gradient>,
+}
+
+impl Default for StrokeStyle {
+    fn default() -> Self {
+        StrokeStyle {
+            width: 1.0,
+            line_cap: LineCap::Butt,
+            dash: Vec::new(),
+            opacity: 1.0,
+            gradient: None,
+        }
+    }
+}
+
+/// The font labels are written in. Png output always uses the bundled font,
+/// whatever the family.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Font {
+    pub family: String,
+    pub height: f32,
+}
+
+impl Default for Font {
+    fn default() -> Self {
+        Font {
+            family: "sans-serif".to_string(),
+            height: 12.0,
+        }
+    }
+}
+
+/// How the drawing is placed in the output image.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Framing {
+    /// Output the canvas as drawn.
+    Canvas,
+    /// Scale the drawing to fill the canvas, leaving a margin around it.
+    Fit { margin: f32 },
+    /// Size the image to the drawing plus some padding.
+    Crop { padding: f32 },
+}
+
+/// How SVG output is written.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SvgOptions {
+    /// Join connected lines of the same color and style into polylines.
+    pub compact: bool,
+    /// Group the elements of each color into a layer of their own.
+    pub color_layers: bool,
+    /// Decimal places kept in coordinates and lengths, up to
+    /// [`MAX_PRECISION`].
+    pub precision: usize,
+}
+
+impl Default for SvgOptions {
+    fn default() -> Self {
+        SvgOptions {
+            compact: false,
+            color_layers: false,
+            precision: 2,
+        }
+    }
+}
+
+/// How an animation of a drawing being built up is timed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Animation {
+    /// Frames shown for each drawing step. Less than one draws several steps
+    /// in each frame.
+    pub frames_per_step: f32,
+    /// Length of the whole animation in seconds.
+    pub duration: f32,
+}
+
+impl Default for Animation {
+    fn default() -> Self {
+        Animation {
+            frames_per_step: 1.0,
+            duration: 5.0,
+        }
+    }
+}
+
+/// The smallest rectangle containing everything drawn so far.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Bounds {
+    min_x: f32,
+    min_y: f32,
+    max_x: f32,
+    max_y: f32,
+}
+
+impl Bounds {
+    fn width(&self) -> f32 {
+        self.max_x - self.min_x
+    }
+
+    fn height(&self) -> f32 {
+        self.max_y - self.min_y
+    }
+}
+
+/// The size of the output image and where the canvas is drawn within it.
+struct Viewport {
+    width: u32,
+    height: u32,
+    scale: f32,
+    offset: (f32, f32),
+}
+
+impl Viewport {
+    fn is_identity(&self) -> bool {
+        self.scale == 1.0 && self.offset == (0.0, 0.0)
+    }
+}
+
+/// A drawn SVG element, the color it is drawn in and the step of the
+/// drawing it was drawn at.
+struct Element {
+    color: Color,
+    markup: String,
+    step: usize,
+}
+
+/// Connected lines that are still being drawn in compact mode.
+struct Polyline {
+    color: Color,
+    style: StrokeStyle,
+    points: Vec<(f32, f32)>,
+}
+
+/// A stamped shape, written once as an SVG symbol and drawn with `<use>`.
+struct Symbol {
+    shape: Vec<Primitive>,
+    markup: Vec<String>,
+    bounds: Option<Bounds>,
+}
+
+/// A raster flood fill, applied after the first `layer` elements are drawn.
+struct FloodFill {
+    layer: usize,
+    step: usize,
+    x: i32,
+    y: i32,
+    color: Color,
+}
+
+/// An image that's being drawn into. The SVG output for plain lines is
+/// identical to `unsvg::Image`, so existing drawings are unchanged, while
+/// strokes can also carry a width, line cap and dash pattern.
+pub struct Canvas {
+    width: u32,
+    height: u32,
+    elements: Vec<Element>,
+    flood_fills: Vec<FloodFill>,
+    symbols: Vec<Symbol>,
+    gradients: Vec<String>,
+    background: Color,
+    bounds: Option<Bounds>,
+    framing: Framing,
+    svg: SvgOptions,
+    polyline: Option<Polyline>,
+    animation: Option<Animation>,
+    antialias: bool,
+    steps: usize,
+    position: (f32, f32),
+    stroke: (Color, StrokeStyle),
+}
+
+impl Canvas {
+    pub fn new(width: u32, height: u32) -> Canvas {
+        Canvas {
+            width,
+            height,
+            elements: Vec::new(),
+            flood_fills: Vec::new(),
+            symbols: Vec::new(),
+            gradients: Vec::new(),
+            background: COLORS[0],
+            bounds: None,
+            framing: Framing::Canvas,
+            svg: SvgOptions::default(),
+            polyline: None,
+            animation: None,
+            antialias: true,
+            steps: 0,
+            position: (0.0, 0.0),
+            stroke: (COLORS[7], StrokeStyle::default()),
+        }
+    }
+
+    pub fn get_dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    pub fn set_framing(&mut self, framing: Framing) {
+        self.framing = framing;
+    }
+
+    pub fn set_background(&mut self, background: Color) {
+        self.background = background;
+    }
+
+    pub fn set_svg_options(&mut self, svg: SvgOptions) {
+        self.svg = svg;
+    }
+
+    pub fn set_animation(&mut self, animation: Option<Animation>) {
+        self.animation = animation;
+    }
+
+    /// Turning antialiasing off draws every pixel of a shape in its full color.
+    pub fn set_antialias(&mut self, antialias: bool) {
+        self.antialias = antialias;
+    }
+
+    /// The ordinal of the next thing drawn.
+    fn next_step(&mut self) -> usize {
+        self.steps += 1;
+        self.steps - 1
+    }
+
+    fn push(&mut self, color: Color, markup: String) {
+        let step = self.next_step();
+        self.elements.push(Element {
+            color,
+            markup,
+            step,
+        });
+    }
+
+    /// Grow the bounding box to include a circle of `radius` around (x, y).
+    fn include(&mut self, (x, y): (f32, f32), radius: f32) {
+        let point = Bounds {
+            min_x: x - radius,
+            min_y: y - radius,
+            max_x: x + radius,
+            max_y: y + radius,
+        };
+        self.bounds = Some(match self.bounds {
+            Some(bounds) => Bounds {
+                min_x: bounds.min_x.min(point.min_x),
+                min_y: bounds.min_y.min(point.min_y),
+                max_x: bounds.max_x.max(point.max_x),
+                max_y: bounds.max_y.max(point.max_y),
+            },
+            None => point,
+        });
+    }
+
+    fn viewport(&self) -> Viewport {
+        let canvas = Viewport {
+            width: self.width,
+            height: self.height,
+            scale: 1.0,
+            offset: (0.0, 0.0),
+        };
+        let Some(bounds) = self.bounds else {
+            return canvas;
+        };
+        match self.framing {
+            Framing::Canvas => canvas,
+            Framing::Fit { margin } => {
+                let available = (
+                    self.width as f32 - 2.0 * margin,
+                    self.height as f32 - 2.0 * margin,
+                );
+                if available.0 <= 0.0 || available.1 <= 0.0 {
+                    return canvas;
+                }
+                let scale = (available.0 / bounds.width().max(f32::EPSILON))
+                    .min(available.1 / bounds.height().max(f32::EPSILON));
+                // Centre the drawing in the space left inside the margin.
+                let offset = (
+                    margin + (available.0 - bounds.width() * scale) / 2.0 - bounds.min_x * scale,
+                    margin + (available.1 - bounds.height() * scale) / 2.0 - bounds.min_y * scale,
+                );
+                Viewport {
+                    scale,
+                    offset,
+                    ..canvas
+                }
+            }
+            Framing::Crop { padding } => Viewport {
+                width: (bounds.width() + 2.0 * padding).ceil().max(1.0) as u32,
+                height: (bounds.height() + 2.0 * padding).ceil().max(1.0) as u32,
+                scale: 1.0,
+                offset: (padding - bounds.min_x, padding - bounds.min_y),
+            },
+        }
+    }
+
+    /// Draw a straight line between two points.
+    pub fn draw_line(
+        &mut self,
+        (x1, y1): (f32, f32),
+        (x2, y2): (f32, f32),
+        color: Color,
+        style: &StrokeStyle,
+    ) {
+        self.include((x1, y1), style.width / 2.0);
+        self.include((x2, y2), style.width / 2.0);
+        let stroke = match style.gradient {
+            Some(gradient) => self.gradient((x1, y1), (x2, y2), (color, gradient.to)),
+            None => hex_color(color),
+        };
+        let precision = self.svg.precision;
+        let markup = format!(
+            r#"<path fill="none" stroke="{stroke}"{} d="M {} {} L {} {}"/>"#,
+            stroke_attributes(style, precision),
+            format_number(x1, precision),
+            format_number(y1, precision),
+            format_number(x2, precision),
+            format_number(y2, precision)
+        );
+        self.push(color, markup);
+    }
+
+    /// A stroke paint fading from the first color at `from` to the second at
+    /// `to`, or just the first color if the points are the same.
+    fn gradient(&mut self, from: (f32, f32), to: (f32, f32), colors: (Color, Color)) -> String {
+        if from == to || colors.0 == colors.1 {
+            return hex_color(colors.0);
+        }
+        let precision = self.svg.precision;
+        let id = format!("gradient-{}", self.gradients.len() + 1);
+        self.gradients.push(format!(
+            r#"<linearGradient id="{id}" gradientUnits="userSpaceOnUse" x1="{}" y1="{}" x2="{}" y2="{}"><stop offset="0" stop-color="{}"/><stop offset="1" stop-color="{}"/></linearGradient>"#,
+            format_number(from.0, precision),
+            format_number(from.1, precision),
+            format_number(to.0, precision),
+            format_number(to.1, precision),
+            hex_color(colors.0),
+            hex_color(colors.1)
+        ));
+        format!("url(#{id})")
+    }
+
+    /// Draw a straight line between two points, continuing the current
+    /// polyline if the line starts where it ends and is drawn the same way.
+    pub fn draw_polyline_segment(
+        &mut self,
+        from: (f32, f32),
+        to: (f32, f32),
+        color: Color,
+        style: &StrokeStyle,
+    ) {
+        self.include(from, style.width / 2.0);
+        self.include(to, style.width / 2.0);
+        match &mut self.polyline {
+            Some(polyline)
+                if polyline.color == color
+                    && polyline.style == *style
+                    && polyline.points.last() == Some(&from) =>
+            {
+                polyline.points.push(to)
+            }
+            _ => {
+                self.end_polyline();
+                self.polyline = Some(Polyline {
+                    color,
+                    style: style.clone(),
+                    points: vec![from, to],
+                });
+            }
+        }
+    }
+
+    /// Write out the polyline being drawn, if there is one. Points after the
+    /// second continue the `L` command, so a single line is written exactly as
+    /// `draw_line` writes it.
+    fn end_polyline(&mut self) {
+        if let Some(polyline) = self.polyline.take() {
+            if let Some(gradient) = polyline.style.gradient {
+                return self.end_gradient_polyline(polyline, gradient);
+            }
+            let precision = self.svg.precision;
+            let points: Vec<String> = polyline
+                .points
+                .iter()
+                .map(|&(x, y)| {
+                    format!(
+                        "{} {}",
+                        format_number(x, precision),
+                        format_number(y, precision)
+                    )
+                })
+                .collect();
+            let markup = format!(
+                r#"<path fill="none" stroke="{}"{} d="M {} L {}"/>"#,
+                hex_color(polyline.color),
+                stroke_attributes(&polyline.style, precision),
+                points[0],
+                points[1..].join(" ")
+            );
+            self.push(polyline.color, markup);
+        }
+    }
+
+    /// Write out a polyline with a gradient stroke, one line at a time so that
+    /// each line can be given its share of the gradient.
+    fn end_gradient_polyline(&mut self, polyline: Polyline, gradient: Gradient) {
+        let lengths: Vec<f32> = polyline
+            .points
+            .windows(2)
+            .map(|line| (line[1].0 - line[0].0).hypot(line[1].1 - line[0].1))
+            .collect();
+        let total: f32 = lengths.iter().sum();
+        let mut drawn = 0.0;
+        for (line, length) in polyline.points.windows(2).zip(lengths) {
+            let colors = match gradient.span {
+                GradientSpan::Segment => (polyline.color, gradient.to),
+                GradientSpan::Path if total > 0.0 => (
+                    mix(polyline.color, gradient.to, drawn / total),
+                    mix(polyline.color, gradient.to, (drawn + length) / total),
+                ),
+                GradientSpan::Path => (polyline.color, polyline.color),
+            };
+            drawn += length;
+            let style = StrokeStyle {
+                gradient: Some(Gradient {
+                    to: colors.1,
+                    ..gradient
+                }),
+                ..polyline.style.clone()
+            };
+            self.draw_line(line[0], line[1], colors.0, &style);
+        }
+    }
+
+    /// Draw a circle centered on (x, y), starting at `heading`.
+    pub fn draw_circle(
+        &mut self,
+        (x, y): (f32, f32),
+        heading: f32,
+        radius: f32,
+        color: Color,
+        style: &StrokeStyle,
+    ) {
+        self.include((x, y), radius + style.width / 2.0);
+        // A gradient runs across the circle, from where it starts to the
+        // point opposite.
+        let stroke = match style.gradient {
+            Some(gradient) => {
+                let radians = |degrees: f32| (degrees - 90.0).to_radians();
+                let point = |degrees: f32| {
+                    (
+                        x + radians(degrees).cos() * radius,
+                        y + radians(degrees).sin() * radius,
+                    )
+                };
+                self.gradient(point(heading), point(heading + 180.0), (color, gradient.to))
+            }
+            None => hex_color(color),
+        };
+        let precision = self.svg.precision;
+        let markup = format!(
+            r#"<circle fill="none" stroke="{stroke}"{} cx="{}" cy="{}" r="{}"/>"#,
+            stroke_attributes(style, precision),
+            format_number(x, precision),
+            format_number(y, precision),
+            format_number(radius, precision)
+        );
+        self.push(color, markup);
+    }
+
+    /// Draw an arc of the circle centered on (x, y), starting at `heading` and
+    /// sweeping clockwise through `angle` degrees (counterclockwise if negative).
+    pub fn draw_arc(
+        &mut self,
+        (x, y): (f32, f32),
+        heading: f32,
+        angle: f32,
+        radius: f32,
+        color: Color,
+        style: &StrokeStyle,
+    ) {
+        if angle.abs() >= 360.0 {
+            return self.draw_circle((x, y), heading, radius, color, style);
+        }
+        let precision = self.svg.precision;
+        let point_at = |degrees: f32| {
+            // Headings start at 0 degrees pointing up and increase clockwise.
+            let radians = (degrees - 90.0).to_radians();
+            (
+                format_number(x + radians.cos() * radius, precision),
+                format_number(y + radians.sin() * radius, precision),
+            )
+        };
+        // The whole circle is a conservative bound for any arc of it.
+        self.include((x, y), radius + style.width / 2.0);
+        let (start_x, start_y) = point_at(heading);
+        let (end_x, end_y) = point_at(heading + angle);
+        // A gradient runs straight from one end of the arc to the other.
+        let stroke = match style.gradient {
+            Some(gradient) => {
+                let radians = |degrees: f32| (degrees - 90.0).to_radians();
+                let end = |degrees: f32| {
+                    (
+                        x + radians(degrees).cos() * radius,
+                        y + radians(degrees).sin() * radius,
+                    )
+                };
+                self.gradient(end(heading), end(heading + angle), (color, gradient.to))
+            }
+            None => hex_color(color),
+        };
+        let large_arc = u8::from(angle.abs() > 180.0);
+        let sweep = u8::from(angle > 0.0);
+        let radius = format_number(radius, precision);
+        let markup = format!(
+            r#"<path fill="none" stroke="{stroke}"{} d="M {start_x} {start_y} A {radius} {radius} 0 {large_arc} {sweep} {end_x} {end_y}"/>"#,
+            stroke_attributes(style, precision)
+        );
+        self.push(color, markup);
+    }
+
+    /// Write `text` with its baseline starting at (x, y), rotated so that it
+    /// reads along `heading`.
+    pub fn draw_text(
+        &mut self,
+        (x, y): (f32, f32),
+        heading: i32,
+        text: &str,
+        font: &Font,
+        color: Color,
+    ) {
+        // Labels are bounded approximately, assuming glyphs are at most as
+        // wide as the font is high.
+        let length = font.height * text.chars().count().max(1) as f32;
+        self.include((x, y), length);
+        let precision = self.svg.precision;
+        let (x, y) = (format_number(x, precision), format_number(y, precision));
+        // Text reads towards 90 degrees (right) when it isn't rotated.
+        let rotation = (heading - 90).rem_euclid(360);
+        let transform = if rotation == 0 {
+            String::new()
+        } else {
+            format!(r#" transform="rotate({rotation} {x} {y})""#)
+        };
+        let markup = format!(
+            r#"<text fill="{}" font-family="{}" font-size="{}" x="{x}" y="{y}"{transform}>{}</text>"#,
+            hex_color(color),
+            escape_xml(&font.family),
+            format_number(font.height, precision),
+            escape_xml(text)
+        );
+        self.push(color, markup);
+    }
+
+    /// Draw `shape` at `position`, turned clockwise through `heading`
+    /// degrees. Each distinct shape is written once, as a symbol.
+    pub fn draw_stamp(&mut self, shape: &[Primitive], position: (f32, f32), heading: f32) {
+        let index = match self.symbols.iter().position(|s| s.shape == shape) {
+            Some(index) => index,
+            None => {
+                let symbol = self.symbol(shape);
+                self.symbols.push(symbol);
+                self.symbols.len() - 1
+            }
+        };
+        if let Some(bounds) = self.symbols[index].bounds {
+            let corners = [
+                (bounds.min_x, bounds.min_y),
+                (bounds.max_x, bounds.min_y),
+                (bounds.max_x, bounds.max_y),
+                (bounds.min_x, bounds.max_y),
+            ];
+            for corner in corners {
+                self.include(place(corner, position, heading), 0.0);
+            }
+        }
+        let precision = self.svg.precision;
+        let rotation = if heading.rem_euclid(360.0) == 0.0 {
+            String::new()
+        } else {
+            format!(" rotate({})", format_number(heading, precision))
+        };
+        let markup = format!(
+            r##"<use href="#shape-{}" transform="translate({} {}){rotation}"/>"##,
+            index + 1,
+            format_number(position.0, precision),
+            format_number(position.1, precision)
+        );
+        let color = shape_color(shape).unwrap_or(COLORS[7]);
+        self.push(color, markup);
+    }
+
+    /// The symbol for a shape, drawn on a canvas of its own. Stamps within
+    /// the shape are drawn in place.
+    fn symbol(&mut self, shape: &[Primitive]) -> Symbol {
+        fn draw_flat(canvas: &mut Canvas, primitive: &Primitive) {
+            match primitive {
+                Primitive::Stamp {
+                    shape,
+                    position,
+                    heading,
+                } => {
+                    for inner in shape {
+                        draw_flat(canvas, &inner.placed(*position, *heading));
+                    }
+                }
+                primitive => draw(canvas, primitive),
+            }
+        }
+
+        let mut canvas = Canvas::new(self.width, self.height);
+        canvas.set_svg_options(SvgOptions {
+            color_layers: false,
+            ..self.svg.clone()
+        });
+        // Gradients are numbered across the whole document.
+        canvas.gradients = std::mem::take(&mut self.gradients);
+        for primitive in shape {
+            draw_flat(&mut canvas, primitive);
+        }
+        canvas.end_polyline();
+        self.gradients = std::mem::take(&mut canvas.gradients);
+        Symbol {
+            shape: shape.to_vec(),
+            markup: canvas.elements.into_iter().map(|e| e.markup).collect(),
+            bounds: canvas.bounds,
+        }
+    }
+
+    /// The number of elements drawn so far, used to place fills underneath
+    /// the strokes that outline them.
+    pub fn layer_count(&self) -> usize {
+        self.elements.len() + usize::from(self.polyline.is_some())
+    }
+
+    /// Fill the polygon through `points`, inserting it at `layer` so that it
+    /// sits below anything drawn after that point.
+    pub fn fill_polygon(&mut self, layer: usize, points: &[(f32, f32)], color: Color) {
+        if points.len() < 3 {
+            return;
+        }
+        for &point in points {
+            self.include(point, 0.0);
+        }
+        let precision = self.svg.precision;
+        let path: Vec<String> = points
+            .iter()
+            .enumerate()
+            .map(|(i, &(x, y))| {
+                let command = if i == 0 { "M" } else { "L" };
+                format!(
+                    "{command} {} {}",
+                    format_number(x, precision),
+                    format_number(y, precision)
+                )
+            })
+            .collect();
+        let layer = layer.min(self.elements.len());
+        let markup = format!(
+            r#"<path fill="{}" stroke="none" d="{} Z"/>"#,
+            hex_color(color),
+            path.join(" ")
+        );
+        let step = self.next_step();
+        self.elements.insert(
+            layer,
+            Element {
+                color,
+                markup,
+                step,
+            },
+        );
+        for fill in self.flood_fills.iter_mut().filter(|f| f.layer > layer) {
+            fill.layer += 1;
+        }
+    }
+
+    /// Flood fill the region around (x, y). This only affects png output.
+    pub fn flood_fill(&mut self, (x, y): (f32, f32), color: Color) {
+        let step = self.next_step();
+        self.flood_fills.push(FloodFill {
+            layer: self.elements.len(),
+            step,
+            x: x.round() as i32,
+            y: y.round() as i32,
+            color,
+        });
+    }
+
+    pub fn has_flood_fills(&self) -> bool {
+        !self.flood_fills.is_empty()
+    }
+
+    /// The SVG document, revealing each element in turn if the drawing is
+    /// animated.
+    pub fn to_svg(&self) -> String {
+        let elements: Vec<&Element> = self.elements.iter().collect();
+        self.document(&elements, true, self.animation.is_some())
+    }
+
+    /// The number of frames in the animation.
+    fn frame_count(&self, animation: &Animation) -> usize {
+        ((self.steps as f32 * animation.frames_per_step).ceil() as usize).max(1)
+    }
+
+    /// The number of steps drawn by the end of `frame`, counting from one.
+    fn steps_shown(&self, frame: usize, frames: usize) -> usize {
+        (self.steps * frame).div_ceil(frames)
+    }
+
+    /// An element's markup, hidden until its step is reached if `animate` is
+    /// set. Each step appears at the start of the first frame it is shown in.
+    fn element_markup(&self, element: &Element, animate: bool) -> String {
+        let Some(animation) = self.animation.filter(|_| animate) else {
+            return element.markup.clone();
+        };
+        let frames = self.frame_count(&animation);
+        let frame = element.step * frames / self.steps;
+        let begin = frame as f32 * animation.duration / frames as f32;
+        format!(
+            r#"<g visibility="hidden"><set attributeName="visibility" to="visible" begin="{}s" fill="freeze"/>{}</g>"#,
+            format_number(begin, 3),
+            element.markup
+        )
+    }
+
+    /// The SVG document for `elements`, framed by the viewport.
+    fn document(&self, elements: &[&Element], background: bool, animate: bool) -> String {
+        let viewport = self.viewport();
+        let (width, height) = (viewport.width, viewport.height);
+        let rendering = if self.antialias {
+            ""
+        } else {
+            " shape-rendering=\"crispEdges\""
+        };
+        let mut svg = format!(
+            "<svg width=\"{width}\" height=\"{height}\" viewBox=\"0 0 {width} {height}\" xmlns=\"http://www.w3.org/2000/svg\"{rendering}>\n"
+        );
+        if self.symbols.is_empty() && self.gradients.is_empty() {
+            svg.push_str("    <defs/>\n");
+        } else {
+            svg.push_str("    <defs>\n");
+            for gradient in &self.gradients {
+                svg.push_str(&format!("        {gradient}\n"));
+            }
+            for (i, symbol) in self.symbols.iter().enumerate() {
+                svg.push_str(&format!(
+                    "        <symbol id=\"shape-{}\" overflow=\"visible\">\n",
+                    i + 1
+                ));
+                for markup in &symbol.markup {
+                    svg.push_str(&format!("            {markup}\n"));
+                }
+                svg.push_str("        </symbol>\n");
+            }
+            svg.push_str("    </defs>\n");
+        }
+        if background {
+            svg.push_str(&format!(
+                "    <path fill=\"{}\" stroke=\"none\" d=\"M 0 0 L {width} 0 L {width} {height} L 0 {height} Z\"/>\n",
+                hex_color(self.background)
+            ));
+        }
+        let mut body: Vec<String> = Vec::new();
+        if self.svg.color_layers {
+            let mut colors: Vec<Color> = Vec::new();
+            for element in elements {
+                if !colors.contains(&element.color) {
+                    colors.push(element.color);
+                }
+            }
+            for color in colors {
+                body.push(format!(r#"<g id="layer-{}">"#, &hex_color(color)[1..]));
+                for element in elements.iter().filter(|e| e.color == color) {
+                    body.push(format!("    {}", self.element_markup(element, animate)));
+                }
+                body.push("</g>".to_string());
+            }
+        } else {
+            body.extend(elements.iter().map(|e| self.element_markup(e, animate)));
+        }
+        if viewport.is_identity() {
+            for line in body {
+                svg.push_str(&format!("    {line}\n"));
+            }
+        } else {
+            let precision = self.svg.precision;
+            svg.push_str(&format!(
+                "    <g transform=\"matrix({} 0 0 {} {} {})\">\n",
+                format_number(viewport.scale, precision),
+                format_number(viewport.scale, precision),
+                format_number(viewport.offset.0, precision),
+                format_number(viewport.offset.1, precision)
+            ));
+            for line in body {
+                svg.push_str(&format!("        {line}\n"));
+            }
+            svg.push_str("    </g>\n");
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    pub fn save_svg<P: AsRef<std::path::Path>>(&self, path: P) -> Result<(), String> {
+        std::fs::write(path, self.to_svg()).map_err(|e| e.to_string())
+    }
+
+    pub fn save_png<P: AsRef<std::path::Path>>(&self, path: P) -> Result<(), String> {
+        let pixmap = self.to_pixmap()?;
+        pixmap.save_png(path).map_err(|e| e.to_string())
+    }
+
+    /// Render the whole drawing as it would be saved to a png.
+    pub fn to_pixmap(&self) -> Result<tiny_skia::Pixmap, String> {
+        self.render(self.steps, &font_database())
+    }
+
+    /// Save each frame of the animation as a png of its own, numbered from
+    /// one after the stem of `path`.
+    pub fn save_png_frames(&self, path: &Path, animation: &Animation) -> Result<(), String> {
+        let fontdb = font_database();
+        let stem = path.with_extension("");
+        let frames = self.frame_count(animation);
+        for frame in 1..=frames {
+            let pixmap = self.render(self.steps_shown(frame, frames), &fontdb)?;
+            let frame_path = format!("{}_{frame:04}.png", stem.display());
+            pixmap.save_png(frame_path).map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    /// Save the animation as a looping gif.
+    pub fn save_gif(&self, path: &Path, animation: &Animation) -> Result<(), String> {
+        let fontdb = font_database();
+        let viewport = self.viewport();
+        let (width, height) = (
+            u16::try_from(viewport.width).map_err(|_| "Image too wide for gif")?,
+            u16::try_from(viewport.height).map_err(|_| "Image too tall for gif")?,
+        );
+        let file = std::fs::File::create(path).map_err(|e| e.to_string())?;
+        let mut encoder = gif::Encoder::new(file, width, height, &[]).map_err(|e| e.to_string())?;
+        encoder
+            .set_repeat(gif::Repeat::Infinite)
+            .map_err(|e| e.to_string())?;
+        let frames = self.frame_count(animation);
+        // Gif frame delays are in hundredths of a second.
+        let delay = (animation.duration * 100.0 / frames as f32)
+            .round()
+            .max(1.0) as u16;
+        for frame in 1..=frames {
+            let mut pixmap = self.render(self.steps_shown(frame, frames), &fontdb)?;
+            let mut frame = gif::Frame::from_rgba_speed(width, height, pixmap.data_mut(), 10);
+            frame.delay = delay;
+            encoder.write_frame(&frame).map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    /// Render the first `steps` steps of the drawing, drawing the elements in
+    /// layers between flood fills so that each fill sees exactly what was
+    /// drawn before it.
+    fn render(&self, steps: usize, fontdb: &fontdb::Database) -> Result<tiny_skia::Pixmap, String> {
+        let viewport = self.viewport();
+        let mut pixmap = tiny_skia::Pixmap::new(viewport.width, viewport.height)
+            .ok_or("Could not create png".to_string())?;
+        let shown = |layers: std::ops::Range<usize>| -> Vec<&Element> {
+            self.elements[layers]
+                .iter()
+                .filter(|e| e.step < steps)
+                .collect()
+        };
+        let mut drawn = 0;
+        let mut background = true;
+        for fill in self.flood_fills.iter().filter(|f| f.step < steps) {
+            let layers = shown(drawn..fill.layer);
+            self.render_elements(&layers, background, fontdb, &mut pixmap)?;
+            drawn = fill.layer;
+            background = false;
+            let x = fill.x as f32 * viewport.scale + viewport.offset.0;
+            let y = fill.y as f32 * viewport.scale + viewport.offset.1;
+            flood_fill(&mut pixmap, x.round() as i32, y.round() as i32, fill.color);
+        }
+        let layers = shown(drawn..self.elements.len());
+        self.render_elements(&layers, background, fontdb, &mut pixmap)?;
+        Ok(pixmap)
+    }
+
+    fn render_elements(
+        &self,
+        elements: &[&Element],
+        background: bool,
+        fontdb: &fontdb::Database,
+        pixmap: &mut tiny_skia::Pixmap,
+    ) -> Result<(), String> {
+        let mut tree = usvg::Tree::from_str(
+            &self.document(elements, background, false),
+            &usvg::Options::default(),
+        )
+        .map_err(|e| e.to_string())?;
+        tree.convert_text(fontdb);
+        resvg::Tree::from_usvg(&tree).render(tiny_skia::Transform::default(), &mut pixmap.as_mut());
+        Ok(())
+    }
+}
+
+impl Renderer for Canvas {
+    fn dimensions(&self) -> (u32, u32) {
+        self.get_dimensions()
+    }
+
+    fn set_stroke(&mut self, color: Color, style: &StrokeStyle) {
+        self.stroke = (color, style.clone());
+    }
+
+    fn move_to(&mut self, point: (f32, f32)) {
+        self.position = point;
+    }
+
+    fn line_to(&mut self, point: (f32, f32)) {
+        let (color, style) = self.stroke.clone();
+        let path_gradient = style
+            .gradient
+            .is_some_and(|gradient| gradient.span == GradientSpan::Path);
+        if self.svg.compact || path_gradient {
+            self.draw_polyline_segment(self.position, point, color, &style);
+        } else {
+            self.end_polyline();
+            self.draw_line(self.position, point, color, &style);
+        }
+        self.position = point;
+    }
+
+    fn arc(&mut self, center: (f32, f32), heading: f32, angle: f32, radius: f32) {
+        self.end_polyline();
+        let (color, style) = self.stroke.clone();
+        self.draw_arc(center, heading, angle, radius, color, &style);
+    }
+
+    fn text(&mut self, position: (f32, f32), heading: i32, text: &str, font: &Font, color: Color) {
+        self.end_polyline();
+        self.draw_text(position, heading, text, font, color);
+    }
+
+    fn layer(&self) -> usize {
+        self.layer_count()
+    }
+
+    fn fill(&mut self, layer: usize, points: &[(f32, f32)], color: Color) {
+        self.end_polyline();
+        self.fill_polygon(layer, points, color);
+    }
+
+    fn flood_fill(&mut self, point: (f32, f32), color: Color) {
+        self.end_polyline();
+        Canvas::flood_fill(self, point, color);
+    }
+
+    fn stamp(&mut self, shape: &[Primitive], position: (f32, f32), heading: f32) {
+        self.end_polyline();
+        self.draw_stamp(shape, position, heading);
+    }
+
+    fn finish(&mut self, path: &Path) -> Result<(), String> {
+        self.end_polyline();
+        match path.extension().and_then(|s| s.to_str()) {
+            Some("svg") => {
+                if self.has_flood_fills() {
+                    return Err(
+                        "Error saving svg: FILL is only supported for png and gif output"
+                            .to_string(),
+                    );
+                }
+                self.save_svg(path)
+                    .map_err(|e| format!("Error saving svg: {e}"))
+            }
+            Some("png") => match &self.animation {
+                Some(animation) => self.save_png_frames(path, animation),
+                None => self.save_png(path),
+            }
+            .map_err(|e| format!("Error saving png: {e}")),
+            Some("gif") => self
+                .save_gif(path, &self.animation.unwrap_or_default())
+                .map_err(|e| format!("Error saving gif: {e}")),
+            _ => Err("File extension not supported".to_string()),
+        }
+    }
+}
+
+/// Fonts available to labels in png output. Only the bundled font is loaded,
+/// so every family, including any set with `SETLABELFONT`, is rasterized with
+/// it and png output looks the same on every machine. Svg output keeps the
+/// family name for the viewer to resolve.
+fn font_database() -> fontdb::Database {
+    let mut fontdb = fontdb::Database::new();
+    fontdb.load_font_data(BUNDLED_FONT.to_vec());
+    fontdb.set_serif_family(BUNDLED_FONT_FAMILY);
+    fontdb.set_sans_serif_family(BUNDLED_FONT_FAMILY);
+    fontdb.set_monospace_family(BUNDLED_FONT_FAMILY);
+    fontdb.set_cursive_family(BUNDLED_FONT_FAMILY);
+    fontdb.set_fantasy_family(BUNDLED_FONT_FAMILY);
+    fontdb
+}
+
+/// Replace the contiguous region of pixels matching the color at (x, y).
+fn flood_fill(pixmap: &mut tiny_skia::Pixmap, x: i32, y: i32, color: Color) {
+    let (width, height) = (pixmap.width() as i32, pixmap.height() as i32);
+    if x < 0 || y < 0 || x >= width || y >= height {
+        return;
+    }
+    let replacement =
+        tiny_skia::ColorU8::from_rgba(color.red, color.green, color.blue, 255).premultiply();
+    let pixels = pixmap.pixels_mut();
+    let target = pixels[(y * width + x) as usize];
+    if target == replacement {
+        return;
+    }
+    let mut stack = vec![(x, y)];
+    while let Some((x, y)) = stack.pop() {
+        if x < 0 || y < 0 || x >= width || y >= height {
+            continue;
+        }
+        let index = (y * width + x) as usize;
+        if pixels[index] != target {
+            continue;
+        }
+        pixels[index] = replacement;
+        stack.extend([(x + 1, y), (x - 1, y), (x, y + 1), (x, y - 1)]);
+    }
+}
+
+/// Formats a number rounded to `precision` decimal places, or to
+/// [`MAX_PRECISION`] if more are asked for.
+pub fn format_number(number: f32, precision: usize) -> String {
+    let factor = 10f32.powi(precision.min(MAX_PRECISION) as i32);
+    let rounded = (number * factor).round() / factor;
+    if rounded == 0.0 {
+        "0".to_string()
+    } else {
+        rounded.to_string()
+    }
+}
+
+/// The color a shape is grouped by in layered svg output, which is the color
+/// of the first thing it draws.
+fn shape_color(shape: &[Primitive]) -> Option<Color> {
+    shape.iter().find_map(|primitive| match primitive {
+        Primitive::Line { color, .. }
+        | Primitive::Arc { color, .. }
+        | Primitive::Text { color, .. }
+        | Primitive::Fill { color, .. }
+        | Primitive::FloodFill { color, .. } => Some(*color),
+        Primitive::Stamp { shape, .. } => shape_color(shape),
+    })
+}
+
+/// The color `amount` of the way from `from` to `to`.
+fn mix(from: Color, to: Color, amount: f32) -> Color {
+    let channel = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * amount).round() as u8;
+    Color {
+        red: channel(from.red, to.red),
+        green: channel(from.green, to.green),
+        blue: channel(from.blue, to.blue),
+    }
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+pub fn hex_color(color: Color) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.red, color.green, color.blue)
+}
+
+/// Stroke attributes that differ from the SVG defaults, in the order usvg
+/// writes them.
+fn stroke_attributes(style: &StrokeStyle, precision: usize) -> String {
+    let mut attributes = String::new();
+    if style.width != 1.0 {
+        attributes.push_str(&format!(
+            r#" stroke-width="{}""#,
+            format_number(style.width, precision)
+        ));
+    }
+    if style.line_cap != LineCap::Butt {
+        attributes.push_str(&format!(r#" stroke-linecap="{}""#, style.line_cap.as_str()));
+    }
+    if !style.dash.is_empty() {
+        let dash: Vec<String> = style
+            .dash
+            .iter()
+            .map(|&d| format_number(d, precision))
+            .collect();
+        attributes.push_str(&format!(r#" stroke-dasharray="{}""#, dash.join(" ")));
+    }
+    if style.opacity != 1.0 {
+        attributes.push_str(&format!(
+            r#" stroke-opacity="{}""#,
+            format_number(style.opacity, precision.max(2))
+        ));
+    }
+    attributes
+}
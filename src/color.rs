@@ -0,0 +1,80 @@
+use unsvg::{Color, COLORS};
+
+use crate::utils::parse_list;
+
+pub const NAMED_COLORS: [(&str, usize); 17] = [
+    ("BLACK", 0),
+    ("BLUE", 1),
+    ("CYAN", 2),
+    ("GREEN", 3),
+    ("RED", 4),
+    ("MAGENTA", 5),
+    ("YELLOW", 6),
+    ("WHITE", 7),
+    ("BROWN", 8),
+    ("TAN", 9),
+    ("FOREST", 10),
+    ("AQUA", 11),
+    ("SALMON", 12),
+    ("PURPLE", 13),
+    ("ORANGE", 14),
+    ("GREY", 15),
+    ("GRAY", 15),
+];
+
+/// The highest index `SETPALETTE` can write to.
+pub const MAX_PALETTE_INDEX: usize = 255;
+
+/// Resolves an evaluated color value into a color. Accepts a palette index,
+/// an RGB list such as `[255 136 0]`, a hex string such as `#ff8800` or one
+/// of the named colors.
+pub fn parse_color(value: &str, palette: &[Color]) -> Result<Color, String> {
+    if let Ok(index) = value.parse::<usize>() {
+        return palette.get(index).copied().ok_or(format!(
+            "Error: Out of bounds access {} is too big. Must be less than {}",
+            index,
+            palette.len()
+        ));
+    }
+    if value.starts_with('[') {
+        return parse_rgb_list(value);
+    }
+    if let Some(hex) = value.strip_prefix('#') {
+        return parse_hex(hex).ok_or(format!("Error: Invalid hex color '{value}'"));
+    }
+    NAMED_COLORS
+        .iter()
+        .find(|(name, _)| *name == value.to_ascii_uppercase())
+        .map(|&(_, index)| COLORS[index])
+        .ok_or(format!("Error: Unknown color '{value}'"))
+}
+
+/// Parses a list of the form `[r g b]` where each component is within 0..=255.
+pub fn parse_rgb_list(value: &str) -> Result<Color, String> {
+    match parse_list::<u8>(value) {
+        Some(rgb) if rgb.len() == 3 => Ok(Color {
+            red: rgb[0],
+            green: rgb[1],
+            blue: rgb[2],
+        }),
+        _ => Err(format!(
+            "Error: Invalid color '{value}'. Expected a list of 3 values between 0 and 255"
+        )),
+    }
+}
+
+fn parse_hex(hex: &str) -> Option<Color> {
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    let component = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some(Color {
+        red: component(0)?,
+        green: component(2)?,
+        blue: component(4)?,
+    })
+}
+
+pub fn format_rgb_list(color: Color) -> String {
+    format!("[{} {} {}]", color.red, color.green, color.blue)
+}
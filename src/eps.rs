@@ -0,0 +1,7 @@
+/// Wraps PostScript page content in an Encapsulated PostScript document of
+/// `width` by `height` points.
+pub fn eps_document(width: u32, height: u32, content: &str) -> String {
+    format!(
+        "%!PS-Adobe-3.0 EPSF-3.0\n%%BoundingBox: 0 0 {width} {height}\n%%EndComments\n{content}\nshowpage\n%%EOF\n"
+    )
+}
@@ -1,78 +1,331 @@
-use unsvg::{get_end_coordinates, Image, COLORS};
+use unsvg::Color;
 
+use crate::canvas::{format_number, Gradient, GradientSpan, LineCap};
+use crate::color::{format_rgb_list, parse_color, parse_rgb_list, MAX_PALETTE_INDEX};
+use crate::display::{draw, DisplayList, Primitive};
+use crate::lsystem::{expand, parse_rules, Action};
+use crate::render::Renderer;
+use crate::shape::Shape;
+use crate::transform::{Transform, YAxis};
 use crate::utils::{
-    exit_with_error, Command, Expression, Procedure, Turtle, Variable, QUERIES, VALUE_PREFIXES,
+    canvas_centre, exit_with_error, parse_list, Command, Environment, Expression, FillRecording,
+    Procedure, Statement, Turtle, Variable, QUERIES, VALUE_PREFIXES,
 };
 
 impl Command {
-    fn execute(&self, turtle: &mut Turtle, image: &mut Image) {
+    fn execute(&self, env: &mut Environment, image: &mut dyn Renderer) {
         match self {
-            Command::PenUp => turtle.pen_down = false,
-            Command::PenDown => turtle.pen_down = true,
+            Command::PenUp => env.turtle_mut().pen_down = false,
+            Command::PenDown => env.turtle_mut().pen_down = true,
+            Command::ShowTurtle => env.turtle_mut().shown = true,
+            Command::HideTurtle => env.turtle_mut().shown = false,
+            Command::Stamp => {
+                let (turtle, transform) = (env.turtle(), env.transform);
+                image.stamp(
+                    &turtle.shape.primitives(turtle.color),
+                    transform.to_canvas((turtle.pos_x, turtle.pos_y)),
+                    turtle.heading as f32,
+                );
+            }
+            Command::SetBackground(expression) => {
+                if let Some(value) = evaluate_expression::<String>(env, expression) {
+                    match parse_color(&value, &env.palette) {
+                        Ok(color) => env.background = color,
+                        Err(e) => exit_with_error(e),
+                    }
+                }
+            }
+            Command::SetShape(expression) => {
+                if let Some(value) = evaluate_expression::<String>(env, expression) {
+                    match parse_shape(env, &value, image.dimensions()) {
+                        Ok(shape) => env.turtle_mut().shape = shape,
+                        Err(e) => exit_with_error(e),
+                    }
+                }
+            }
             Command::Forward(expression) => {
-                if let Some(length) = evaluate_expression::<i32>(turtle, expression) {
-                    pen_move(image, turtle, turtle.heading, length);
+                if let Some(length) = evaluate_number(env, expression) {
+                    let transform = env.transform;
+                    let turtle = env.turtle_mut();
+                    pen_move(image, &transform, turtle, turtle.heading, length);
                 }
             }
             Command::Back(expression) => {
-                if let Some(length) = evaluate_expression::<i32>(turtle, expression) {
-                    pen_move(image, turtle, turtle.heading + 180, length);
+                if let Some(length) = evaluate_number(env, expression) {
+                    let transform = env.transform;
+                    let turtle = env.turtle_mut();
+                    pen_move(image, &transform, turtle, turtle.heading + 180, length);
                 }
             }
             Command::Left(expression) => {
-                if let Some(length) = evaluate_expression::<i32>(turtle, expression) {
-                    pen_move(image, turtle, turtle.heading - 90, length);
+                if let Some(length) = evaluate_number(env, expression) {
+                    let transform = env.transform;
+                    let turtle = env.turtle_mut();
+                    pen_move(image, &transform, turtle, turtle.heading - 90, length);
                 }
             }
             Command::Right(expression) => {
-                if let Some(length) = evaluate_expression::<i32>(turtle, expression) {
-                    pen_move(image, turtle, turtle.heading + 90, length);
+                if let Some(length) = evaluate_number(env, expression) {
+                    let transform = env.transform;
+                    let turtle = env.turtle_mut();
+                    pen_move(image, &transform, turtle, turtle.heading + 90, length);
                 }
             }
             Command::SetPenColor(expression) => {
-                if let Some(color_index) = evaluate_expression::<usize>(turtle, expression) {
-                    if color_index < COLORS.len() {
-                        turtle.color = COLORS[color_index];
+                if let Some(value) = evaluate_expression::<String>(env, expression) {
+                    match parse_color(&value, &env.palette) {
+                        Ok(color) => env.turtle_mut().color = color,
+                        Err(e) => exit_with_error(e),
+                    }
+                }
+            }
+            Command::SetPalette((index, value)) => {
+                if let Some(index) = evaluate_expression::<usize>(env, index) {
+                    if index > MAX_PALETTE_INDEX {
+                        exit_with_error(format!(
+                            "Error: Palette index {index} is too big. Must be at most {MAX_PALETTE_INDEX}"
+                        ));
+                    } else if let Some(value) = evaluate_expression::<String>(env, value) {
+                        match parse_rgb_list(&value) {
+                            Ok(color) => {
+                                if index >= env.palette.len() {
+                                    env.palette.resize(index + 1, Color::black());
+                                }
+                                env.palette[index] = color;
+                            }
+                            Err(e) => exit_with_error(e),
+                        }
+                    }
+                }
+            }
+            Command::SetPenSize(expression) => {
+                if let Some(width) = evaluate_expression::<f32>(env, expression) {
+                    if width.is_finite() && width >= 0.0 {
+                        env.turtle_mut().stroke.width = width;
                     } else {
                         exit_with_error(format!(
-                            "Error: Out of bounds access {} is too big. Must be less than {}",
-                            color_index,
-                            COLORS.len()
+                            "Error: Pen size {width} must be a finite, non-negative number"
                         ));
                     }
                 }
             }
+            Command::SetLineCap(expression) => {
+                if let Some(value) = evaluate_expression::<String>(env, expression) {
+                    match value.parse::<LineCap>() {
+                        Ok(line_cap) => env.turtle_mut().stroke.line_cap = line_cap,
+                        Err(e) => exit_with_error(e),
+                    }
+                }
+            }
+            Command::SetDash(expression) => {
+                if let Some(value) = evaluate_expression::<String>(env, expression) {
+                    match parse_list::<f32>(&value) {
+                        Some(dash) if dash.iter().all(|&d| d.is_finite() && d >= 0.0) => {
+                            // An all-zero pattern would hide the line, so treat it as solid.
+                            if dash.iter().all(|&d| d == 0.0) {
+                                env.turtle_mut().stroke.dash = Vec::new();
+                            } else {
+                                env.turtle_mut().stroke.dash = dash;
+                            }
+                        }
+                        _ => exit_with_error(format!(
+                            "Error: Invalid dash pattern '{value}'. Expected a list of non-negative lengths"
+                        )),
+                    }
+                }
+            }
+            Command::SetPenAlpha(expression) => {
+                if let Some(alpha) = evaluate_expression::<f32>(env, expression) {
+                    if (0.0..=1.0).contains(&alpha) {
+                        env.turtle_mut().stroke.opacity = alpha;
+                    } else {
+                        exit_with_error(format!(
+                            "Error: Pen alpha {alpha} must be between 0 and 1"
+                        ));
+                    }
+                }
+            }
+            Command::SetPenGradient((color, span)) => {
+                if let (Some(color), Some(span)) = (
+                    evaluate_expression::<String>(env, color),
+                    evaluate_expression::<String>(env, span),
+                ) {
+                    let gradient = parse_color(&color, &env.palette).and_then(|to| {
+                        Ok(Gradient {
+                            to,
+                            span: span.parse::<GradientSpan>()?,
+                        })
+                    });
+                    match gradient {
+                        Ok(gradient) => env.turtle_mut().stroke.gradient = Some(gradient),
+                        Err(e) => exit_with_error(e),
+                    }
+                }
+            }
+            Command::ClearPenGradient => env.turtle_mut().stroke.gradient = None,
+            Command::SetFillColor(expression) => {
+                if let Some(value) = evaluate_expression::<String>(env, expression) {
+                    match parse_color(&value, &env.palette) {
+                        Ok(color) => env.turtle_mut().fill_color = color,
+                        Err(e) => exit_with_error(e),
+                    }
+                }
+            }
+            Command::BeginFill => {
+                let transform = env.transform;
+                let turtle = env.turtle_mut();
+                if turtle.fill.is_some() {
+                    exit_with_error("Error: BEGINFILL called while already filling".to_string());
+                }
+                turtle.fill = Some(FillRecording {
+                    layer: image.layer(),
+                    points: vec![transform.to_canvas((turtle.pos_x, turtle.pos_y))],
+                });
+            }
+            Command::EndFill => {
+                let turtle = env.turtle_mut();
+                match turtle.fill.take() {
+                    Some(fill) => image.fill(fill.layer, &fill.points, turtle.fill_color),
+                    None => exit_with_error("Error: ENDFILL called without BEGINFILL".to_string()),
+                }
+            }
+            Command::Fill => {
+                let turtle = env.turtle();
+                let position = env.transform.to_canvas((turtle.pos_x, turtle.pos_y));
+                image.flood_fill(position, turtle.fill_color);
+            }
             Command::Turn(expression) => {
-                if let Some(degrees) = evaluate_expression::<i32>(turtle, expression) {
-                    turtle.heading += degrees;
+                if let Some(degrees) = evaluate_expression::<i32>(env, expression) {
+                    env.turtle_mut().heading += degrees;
+                }
+            }
+            Command::Arc((angle, radius)) => {
+                if let Some(angle) = evaluate_number(env, angle) {
+                    if let Some(radius) = evaluate_radius(env, radius) {
+                        let (turtle, transform) = (env.turtle(), env.transform);
+                        if turtle.pen_down {
+                            image.set_stroke(turtle.color, &turtle.stroke);
+                            image.arc(
+                                transform.to_canvas((turtle.pos_x, turtle.pos_y)),
+                                turtle.heading as f32,
+                                angle,
+                                transform.to_canvas_length(radius),
+                            );
+                        }
+                    }
+                }
+            }
+            Command::Circle(radius) => {
+                if let Some(radius) = evaluate_radius(env, radius) {
+                    let (turtle, transform) = (env.turtle(), env.transform);
+                    if turtle.pen_down {
+                        image.set_stroke(turtle.color, &turtle.stroke);
+                        image.arc(
+                            transform.to_canvas((turtle.pos_x, turtle.pos_y)),
+                            0.0,
+                            360.0,
+                            transform.to_canvas_length(radius),
+                        );
+                    }
+                }
+            }
+            Command::Label(expression) => {
+                if let Some(value) = evaluate_expression::<String>(env, expression) {
+                    let (turtle, transform) = (env.turtle(), env.transform);
+                    image.text(
+                        transform.to_canvas((turtle.pos_x, turtle.pos_y)),
+                        turtle.heading,
+                        list_to_words(&value),
+                        &turtle.label_font,
+                        turtle.color,
+                    );
+                }
+            }
+            Command::SetLabelFont(expression) => {
+                if let Some(family) = evaluate_expression::<String>(env, expression) {
+                    env.turtle_mut().label_font.family = list_to_words(&family).to_string();
+                }
+            }
+            Command::SetLabelHeight(expression) => {
+                if let Some(height) = evaluate_expression::<f32>(env, expression) {
+                    if height > 0.0 {
+                        env.turtle_mut().label_font.height = height;
+                    } else {
+                        exit_with_error(format!("Error: Label height {height} must be positive"));
+                    }
                 }
             }
             Command::SetHeading(expression) => {
-                if let Some(degrees) = evaluate_expression::<i32>(turtle, expression) {
-                    turtle.heading = degrees;
+                if let Some(degrees) = evaluate_expression::<i32>(env, expression) {
+                    env.turtle_mut().heading = degrees;
+                }
+            }
+            Command::SetScale(expression) => {
+                if let Some(units) = evaluate_number(env, expression) {
+                    if units > 0.0 {
+                        // The scale is relative to the canvas, so drawings look the same at
+                        // every image size.
+                        let (width, height) = image.dimensions();
+                        let scale = width.min(height) as f32 / units;
+                        env.set_transform(Transform {
+                            scale,
+                            ..env.transform
+                        });
+                    } else {
+                        exit_with_error(format!("Error: Scale {units} must be positive"));
+                    }
+                }
+            }
+            Command::SetOrigin((x, y)) => {
+                if let Some(x) = evaluate_number(env, x) {
+                    if let Some(y) = evaluate_number(env, y) {
+                        // The origin is given in user units from the centre of the canvas.
+                        let (width, height) = image.dimensions();
+                        let centre = Transform {
+                            origin: (width as f32 / 2.0, height as f32 / 2.0),
+                            ..env.transform
+                        };
+                        env.set_transform(Transform {
+                            origin: centre.to_canvas((x, y)),
+                            ..env.transform
+                        });
+                    }
+                }
+            }
+            Command::SetYAxis(expression) => {
+                if let Some(value) = evaluate_expression::<String>(env, expression) {
+                    match value.parse::<YAxis>() {
+                        Ok(y_axis) => env.set_transform(Transform {
+                            y_axis,
+                            ..env.transform
+                        }),
+                        Err(e) => exit_with_error(e),
+                    }
                 }
             }
             Command::SetX(expression) => {
-                if let Some(x) = evaluate_expression::<i32>(turtle, expression) {
-                    turtle.pos_x = x;
+                if let Some(x) = evaluate_number(env, expression) {
+                    let transform = env.transform;
+                    let turtle = env.turtle_mut();
+                    (turtle.pos_x, turtle.pos_y) = transform.snap((x, turtle.pos_y));
+                    record_fill_point(turtle, &transform);
                 }
             }
             Command::SetY(expression) => {
-                if let Some(y) = evaluate_expression::<i32>(turtle, expression) {
-                    turtle.pos_y = y;
+                if let Some(y) = evaluate_number(env, expression) {
+                    let transform = env.transform;
+                    let turtle = env.turtle_mut();
+                    (turtle.pos_x, turtle.pos_y) = transform.snap((turtle.pos_x, y));
+                    record_fill_point(turtle, &transform);
                 }
             }
             Command::Make((name, value)) => {
-                if let Some(validated_name) = evaluate_expression::<String>(turtle, name) {
-                    if let Some(validated_value) = evaluate_expression::<String>(turtle, value) {
-                        if let Some(existing_variable) = turtle
-                            .variables
-                            .iter_mut()
-                            .find(|v| v.name == validated_name)
-                        {
+                if let Some(validated_name) = evaluate_expression::<String>(env, name) {
+                    if let Some(validated_value) = evaluate_expression::<String>(env, value) {
+                        if let Some(existing_variable) = env.variable_mut(&validated_name) {
                             existing_variable.value = validated_value;
                         } else {
-                            turtle.variables.push(Variable {
+                            env.variables.push(Variable {
                                 name: validated_name,
                                 value: validated_value,
                             });
@@ -81,13 +334,9 @@ impl Command {
                 }
             }
             Command::AddAssign((name, value)) => {
-                if let Some(validated_name) = evaluate_expression::<String>(turtle, name) {
-                    if let Some(validated_value) = evaluate_expression::<i32>(turtle, value) {
-                        if let Some(existing_variable) = turtle
-                            .variables
-                            .iter_mut()
-                            .find(|v| v.name == validated_name)
-                        {
+                if let Some(validated_name) = evaluate_expression::<String>(env, name) {
+                    if let Some(validated_value) = evaluate_expression::<i32>(env, value) {
+                        if let Some(existing_variable) = env.variable_mut(&validated_name) {
                             if let Ok(curr_value) = existing_variable.value.parse::<i32>() {
                                 existing_variable.value = (curr_value + validated_value).to_string()
                             }
@@ -96,26 +345,92 @@ impl Command {
                 }
             }
             Command::If((expression, commands)) => {
-                if let Some(is_true) = evaluate_expression::<bool>(turtle, expression) {
+                if let Some(is_true) = evaluate_expression::<bool>(env, expression) {
                     if is_true {
-                        execute_commands(turtle, commands, image);
+                        execute_commands(env, commands, image);
                     }
                 }
             }
             Command::While((expression, commands)) => loop {
-                if let Some(is_true) = evaluate_expression::<bool>(turtle, expression) {
+                if let Some(is_true) = evaluate_expression::<bool>(env, expression) {
                     if is_true {
-                        execute_commands(turtle, commands, image);
+                        execute_commands(env, commands, image);
                     } else {
                         break;
                     }
                 }
             },
+            Command::LSystem((axiom, rules, iterations, angle, step)) => {
+                let axiom = evaluate_expression::<String>(env, axiom);
+                let rules = evaluate_expression::<String>(env, rules);
+                let iterations = evaluate_expression::<usize>(env, iterations);
+                let angle = evaluate_expression::<i32>(env, angle);
+                let step = evaluate_number(env, step);
+                if let (Some(axiom), Some(rules), Some(iterations), Some(angle), Some(step)) =
+                    (axiom, rules, iterations, angle, step)
+                {
+                    match parse_rules(&rules).and_then(|rules| expand(&axiom, &rules, iterations)) {
+                        Ok(symbols) => run_lsystem(env, &symbols, angle, step, image),
+                        Err(e) => exit_with_error(e),
+                    }
+                }
+            }
+            Command::LSystemAction((symbol, action)) => {
+                if let Some(symbol) = evaluate_expression::<char>(env, symbol) {
+                    if let Some(action) = evaluate_expression::<String>(env, action) {
+                        match action.parse::<Action>() {
+                            Ok(action) => {
+                                match env.lsystem_actions.iter_mut().find(|(s, _)| *s == symbol) {
+                                    Some(existing) => existing.1 = action,
+                                    None => env.lsystem_actions.push((symbol, action)),
+                                }
+                            }
+                            Err(e) => exit_with_error(e),
+                        }
+                    }
+                }
+            }
+            Command::PushTurtle => env.turtle_mut().push_state(),
+            Command::PopTurtle => {
+                let transform = env.transform;
+                let turtle = env.turtle_mut();
+                if turtle.pop_state() {
+                    record_fill_point(turtle, &transform);
+                } else {
+                    exit_with_error(
+                        "Error: POPTURTLE called with no saved turtle state".to_string(),
+                    );
+                }
+            }
+            Command::NewTurtle(expression) => {
+                if let Some(name) = evaluate_expression::<String>(env, expression) {
+                    if env.find_turtle(&name).is_some() {
+                        exit_with_error(format!("Error: Turtle '{name}' already exists"));
+                    }
+                    // New turtles start in the middle of the canvas, wherever
+                    // that is in user space.
+                    let (x, y) = env.transform.to_user(canvas_centre(image.dimensions()));
+                    env.turtles.push(Turtle::new(name, x, y));
+                }
+            }
+            Command::Tell(expression) => {
+                if let Some(index) = evaluate_turtle(env, expression) {
+                    env.active = index;
+                }
+            }
+            Command::Ask((expression, commands)) => {
+                if let Some(index) = evaluate_turtle(env, expression) {
+                    let original_turtle = env.active;
+                    env.active = index;
+                    execute_commands(env, commands, image);
+                    env.active = original_turtle;
+                }
+            }
             Command::To((name, args, commands)) => {
                 if let Ok(arg_strings) = args
                     .iter()
                     .map(|arg| {
-                        evaluate_expression::<String>(turtle, arg)
+                        evaluate_expression::<String>(env, arg)
                             .ok_or_else(|| "Evaluation failed in procedure argument".to_string())
                     })
                     .collect::<Result<Vec<String>, String>>()
@@ -125,13 +440,13 @@ impl Command {
                         args: arg_strings,
                         commands: commands.clone(),
                     };
-                    turtle.procedures.push(procedure);
+                    env.procedures.push(procedure);
                 } else {
                     exit_with_error("Evaluation failed in procedure argument".to_string());
                 }
             }
             Command::Procedure((name, args)) => {
-                if let Some(procedure) = turtle
+                if let Some(procedure) = env
                     .procedures
                     .clone()
                     .iter()
@@ -147,7 +462,7 @@ impl Command {
                     }
                     let evaluated_args = args
                         .iter()
-                        .map(|arg| match evaluate_expression::<String>(turtle, arg) {
+                        .map(|arg| match evaluate_expression::<String>(env, arg) {
                             Some(value) => value,
                             None => {
                                 exit_with_error(format!(
@@ -157,15 +472,15 @@ impl Command {
                             }
                         })
                         .collect::<Vec<String>>();
-                    let original_variables = turtle.variables.clone();
+                    let original_variables = env.variables.clone();
                     for (i, arg) in evaluated_args.iter().enumerate() {
-                        turtle.variables.push(Variable {
+                        env.variables.push(Variable {
                             name: arg_names[i].clone(),
                             value: arg.to_string(),
                         });
                     }
-                    execute_commands(turtle, &procedure.commands, image);
-                    turtle.variables = original_variables;
+                    execute_commands(env, &procedure.commands, image);
+                    env.variables = original_variables;
                 } else {
                     exit_with_error(format!("Error: unknown command '{name}'"));
                 }
@@ -174,72 +489,170 @@ impl Command {
     }
 }
 
-fn evaluate_expression<T>(turtle: &mut Turtle, expression: &Expression) -> Option<T>
+/// Drives the active turtle through an expanded L-system string.
+fn run_lsystem(
+    env: &mut Environment,
+    symbols: &str,
+    angle: i32,
+    step: f32,
+    image: &mut dyn Renderer,
+) {
+    for symbol in symbols.chars() {
+        let action = env
+            .lsystem_actions
+            .iter()
+            .find(|(s, _)| *s == symbol)
+            .map(|(_, action)| action.clone());
+        let transform = env.transform;
+        let turtle = env.turtle_mut();
+        match action {
+            Some(Action::Forward) => pen_move(image, &transform, turtle, turtle.heading, step),
+            Some(Action::Move) => {
+                let pen_down = turtle.pen_down;
+                turtle.pen_down = false;
+                pen_move(image, &transform, turtle, turtle.heading, step);
+                turtle.pen_down = pen_down;
+            }
+            Some(Action::Left) => turtle.heading -= angle,
+            Some(Action::Right) => turtle.heading += angle,
+            Some(Action::TurnAround) => turtle.heading += 180,
+            Some(Action::Push) => turtle.push_state(),
+            Some(Action::Pop) => {
+                if turtle.pop_state() {
+                    record_fill_point(turtle, &transform);
+                } else {
+                    exit_with_error(format!("Error: Unmatched '{symbol}' in L-system"));
+                }
+            }
+            Some(Action::Procedure(name)) => {
+                Command::Procedure((name, Vec::new())).execute(env, image)
+            }
+            Some(Action::Ignore) | None => {}
+        }
+    }
+}
+
+/// Lists are written as their words separated by spaces.
+fn list_to_words(value: &str) -> &str {
+    value
+        .strip_prefix('[')
+        .and_then(|v| v.strip_suffix(']'))
+        .unwrap_or(value)
+}
+
+/// Evaluates a turtle name into the index of that turtle.
+fn evaluate_turtle(env: &mut Environment, expression: &Expression) -> Option<usize> {
+    let name = evaluate_expression::<String>(env, expression)?;
+    let index = env.find_turtle(&name);
+    if index.is_none() {
+        exit_with_error(format!("Error: Turtle '{name}' not found"));
+    }
+    index
+}
+
+/// Evaluates a number of user units or degrees, which must be finite to be
+/// drawn.
+fn evaluate_number(env: &mut Environment, expression: &Expression) -> Option<f32> {
+    let value = evaluate_expression::<f32>(env, expression)?;
+    if !value.is_finite() {
+        exit_with_error(format!("Error: {value} is not a finite number"));
+    }
+    Some(value)
+}
+
+fn evaluate_radius(env: &mut Environment, expression: &Expression) -> Option<f32> {
+    let radius = evaluate_number(env, expression)?;
+    if radius < 0.0 {
+        exit_with_error(format!("Error: Radius {radius} must not be negative"));
+    }
+    Some(radius)
+}
+
+fn evaluate_expression<T>(env: &mut Environment, expression: &Expression) -> Option<T>
 where
     T: std::str::FromStr + PartialEq + PartialOrd,
 {
     match expression {
         Expression::Eq([left, right]) => {
-            let left = evaluate_expression::<String>(turtle, left)?;
-            let right = evaluate_expression::<String>(turtle, right)?;
-            convert_bool_to_t::<T>(left == right)
+            let left = evaluate_expression::<String>(env, left)?;
+            let right = evaluate_expression::<String>(env, right)?;
+            convert_bool_to_t::<T>(values_equal(&left, &right))
         }
         Expression::Ne([left, right]) => {
-            let left = evaluate_expression::<String>(turtle, left)?;
-            let right = evaluate_expression::<String>(turtle, right)?;
-            convert_bool_to_t::<T>(left != right)
+            let left = evaluate_expression::<String>(env, left)?;
+            let right = evaluate_expression::<String>(env, right)?;
+            convert_bool_to_t::<T>(!values_equal(&left, &right))
         }
         Expression::Gt([left, right]) => {
-            let left = evaluate_expression::<i32>(turtle, left)?;
-            let right = evaluate_expression::<i32>(turtle, right)?;
+            let left = evaluate_number(env, left)?;
+            let right = evaluate_number(env, right)?;
             convert_bool_to_t::<T>(left > right)
         }
         Expression::Lt([left, right]) => {
-            let left = evaluate_expression::<i32>(turtle, left)?;
-            let right = evaluate_expression::<i32>(turtle, right)?;
+            let left = evaluate_number(env, left)?;
+            let right = evaluate_number(env, right)?;
             convert_bool_to_t::<T>(left < right)
         }
         Expression::And([left, right]) => {
-            let left = evaluate_expression::<bool>(turtle, left)?;
-            let right = evaluate_expression::<bool>(turtle, right)?;
+            let left = evaluate_expression::<bool>(env, left)?;
+            let right = evaluate_expression::<bool>(env, right)?;
             convert_bool_to_t::<T>(left && right)
         }
         Expression::Or([left, right]) => {
-            let left = evaluate_expression::<bool>(turtle, left)?;
-            let right = evaluate_expression::<bool>(turtle, right)?;
+            let left = evaluate_expression::<bool>(env, left)?;
+            let right = evaluate_expression::<bool>(env, right)?;
             convert_bool_to_t::<T>(left || right)
         }
         Expression::Add([left, right]) => {
-            let left = evaluate_expression::<i32>(turtle, left)?;
-            let right = evaluate_expression::<i32>(turtle, right)?;
-            let sum = left + right;
-            T::from_str(&sum.to_string()).ok()
+            let left = evaluate_number(env, left)?;
+            let right = evaluate_number(env, right)?;
+            T::from_str(&format_coordinate(left + right)).ok()
         }
         Expression::Subtract([left, right]) => {
-            let left = evaluate_expression::<i32>(turtle, left)?;
-            let right = evaluate_expression::<i32>(turtle, right)?;
-            let difference = left - right;
-            T::from_str(&difference.to_string()).ok()
+            let left = evaluate_number(env, left)?;
+            let right = evaluate_number(env, right)?;
+            T::from_str(&format_coordinate(left - right)).ok()
         }
         Expression::Multiply([left, right]) => {
-            let left = evaluate_expression::<i32>(turtle, left)?;
-            let right = evaluate_expression::<i32>(turtle, right)?;
-            let product = left * right;
-            T::from_str(&product.to_string()).ok()
+            let left = evaluate_number(env, left)?;
+            let right = evaluate_number(env, right)?;
+            T::from_str(&format_coordinate(left * right)).ok()
         }
         Expression::Divide([left, right]) => {
-            let left = evaluate_expression::<i32>(turtle, left)?;
-            let right = evaluate_expression::<i32>(turtle, right)?;
-            if right != 0 {
-                let quotient = left / right;
-                T::from_str(&quotient.to_string()).ok()
+            let left = evaluate_number(env, left)?;
+            let right = evaluate_number(env, right)?;
+            if right != 0.0 {
+                T::from_str(&format_coordinate(left / right)).ok()
             } else {
                 exit_with_error("Error: cannot divide by 0".to_string());
                 None
             }
         }
+        Expression::Towards([x, y]) => {
+            let x = evaluate_number(env, x)?;
+            let y = evaluate_number(env, y)?;
+            let turtle = env.turtle();
+            let heading = env
+                .transform
+                .heading_between((turtle.pos_x, turtle.pos_y), (x, y));
+            T::from_str(&heading.to_string()).ok()
+        }
+        Expression::Distance([x, y]) => {
+            let x = evaluate_number(env, x)?;
+            let y = evaluate_number(env, y)?;
+            let dx = x - env.turtle().pos_x;
+            let dy = y - env.turtle().pos_y;
+            T::from_str(&format_coordinate(dx.hypot(dy))).ok()
+        }
+        Expression::List(items) => {
+            let values = items
+                .iter()
+                .map(|item| evaluate_expression::<String>(env, item))
+                .collect::<Option<Vec<String>>>()?;
+            T::from_str(&format!("[{}]", values.join(" "))).ok()
+        }
         Expression::Value(str) => {
-            if let Some(value) = get_value_from_string::<T>(turtle, str.clone()) {
+            if let Some(value) = get_value_from_string::<T>(env, str.clone()) {
                 Some(value)
             } else {
                 exit_with_error("Error: idk how you got here".to_string());
@@ -249,11 +662,28 @@ where
     }
 }
 
+/// Numbers, including coordinates and distances, are reported to 4 decimal
+/// places so that exact positions don't show floating point noise. Whole
+/// numbers are written without a decimal point, so they can still be used
+/// wherever a whole number is expected.
+fn format_coordinate(value: f32) -> String {
+    format_number(value, 4)
+}
+
+/// Values are equal if they are the same word, or the same number however
+/// it's written, so `"2` equals `/ "4 "2` and `XCOR` equals `"25`.
+fn values_equal(left: &str, right: &str) -> bool {
+    match (left.parse::<f32>(), right.parse::<f32>()) {
+        (Ok(left), Ok(right)) if left.is_finite() && right.is_finite() => left == right,
+        _ => left == right,
+    }
+}
+
 fn convert_bool_to_t<T: std::str::FromStr>(value: bool) -> Option<T> {
     T::from_str(if value { "true" } else { "false" }).ok()
 }
 
-fn get_value_from_string<T: std::str::FromStr>(turtle: &mut Turtle, str: String) -> Option<T> {
+fn get_value_from_string<T: std::str::FromStr>(env: &mut Environment, str: String) -> Option<T> {
     for &prefix in VALUE_PREFIXES.iter() {
         if str.starts_with(prefix) {
             match prefix {
@@ -269,11 +699,7 @@ fn get_value_from_string<T: std::str::FromStr>(turtle: &mut Turtle, str: String)
                 }
                 ":" => {
                     if let Some(stripped) = str.strip_prefix(":") {
-                        if let Some(variable) = turtle
-                            .variables
-                            .iter()
-                            .find(|variable| variable.name == stripped)
-                        {
+                        if let Some(variable) = env.variable(stripped) {
                             match T::from_str(&variable.value) {
                                 Ok(res) => return Some(res),
                                 Err(_) => {
@@ -289,16 +715,36 @@ fn get_value_from_string<T: std::str::FromStr>(turtle: &mut Turtle, str: String)
             }
         }
     }
+    let turtle = env.turtle();
     for &query in QUERIES.iter() {
         if str == query {
             match query {
-                "XCOR" => return T::from_str(&turtle.pos_x.to_string()).ok(),
-                "YCOR" => return T::from_str(&turtle.pos_y.to_string()).ok(),
+                "XCOR" => return T::from_str(&format_coordinate(turtle.pos_x)).ok(),
+                "YCOR" => return T::from_str(&format_coordinate(turtle.pos_y)).ok(),
                 "HEADING" => return T::from_str(&turtle.heading.to_string()).ok(),
                 "COLOR" => {
-                    if let Some(pos) = COLORS.iter().position(|&color| color == turtle.color) {
-                        return T::from_str(&pos.to_string()).ok();
-                    }
+                    return match env.palette.iter().position(|&c| c == turtle.color) {
+                        Some(pos) => T::from_str(&pos.to_string()).ok(),
+                        None => T::from_str(&format_rgb_list(turtle.color)).ok(),
+                    };
+                }
+                "POS" => {
+                    return T::from_str(&format!(
+                        "[{} {}]",
+                        format_coordinate(turtle.pos_x),
+                        format_coordinate(turtle.pos_y)
+                    ))
+                    .ok()
+                }
+                "PENDOWNP" => return T::from_str(&turtle.pen_down.to_string()).ok(),
+                "PENCOLOR" => return T::from_str(&format_rgb_list(turtle.color)).ok(),
+                "WHO" => return T::from_str(&turtle.name).ok(),
+                "SHOWNP" => return T::from_str(&turtle.shown.to_string()).ok(),
+                "BACKGROUND" => {
+                    return match env.palette.iter().position(|&c| c == env.background) {
+                        Some(pos) => T::from_str(&pos.to_string()).ok(),
+                        None => T::from_str(&format_rgb_list(env.background)).ok(),
+                    };
                 }
                 _ => todo!("Add queries and logic here"),
             }
@@ -307,30 +753,136 @@ fn get_value_from_string<T: std::str::FromStr>(turtle: &mut Turtle, str: String)
     None
 }
 
-fn pen_move(image: &mut Image, turtle: &mut Turtle, heading: i32, length: i32) {
-    let result: Result<(i32, i32), String> = if turtle.pen_down {
-        image.draw_simple_line(turtle.pos_x, turtle.pos_y, heading, length, turtle.color)
-    } else {
-        Ok(get_end_coordinates(
-            turtle.pos_x,
-            turtle.pos_y,
-            heading,
-            length,
-        ))
-    };
-    match result {
-        Ok((x, y)) => {
-            turtle.pos_x = x;
-            turtle.pos_y = y;
+/// Draws every shown turtle in its shape, pointing along its heading. The
+/// default triangle is the same size in pixels whatever the transform.
+pub fn draw_turtles(env: &Environment, image: &mut dyn Renderer) {
+    image.set_line(0);
+    for turtle in env.turtles.iter().filter(|turtle| turtle.shown) {
+        let position = env.transform.to_canvas((turtle.pos_x, turtle.pos_y));
+        for primitive in turtle.shape.primitives(turtle.color) {
+            draw(image, &primitive.placed(position, turtle.heading as f32));
         }
-        Err(e) => {
-            exit_with_error(e);
+    }
+}
+
+/// A shape from a list of points, given as distances to the turtle's right
+/// and ahead of it, or from the name of a procedure that draws it.
+fn parse_shape(
+    env: &mut Environment,
+    value: &str,
+    dimensions: (u32, u32),
+) -> Result<Shape, String> {
+    if value.starts_with('[') {
+        return match parse_list::<f32>(value) {
+            Some(coordinates) if coordinates.len() >= 6 && coordinates.len() % 2 == 0 => {
+                let scale = env.transform.scale;
+                Ok(Shape::Polygon(
+                    coordinates
+                        .chunks(2)
+                        .map(|point| (point[0] * scale, -point[1] * scale))
+                        .collect(),
+                ))
+            }
+            _ => Err(format!(
+                "Error: Invalid shape '{value}'. Expected a list of at least 3 points"
+            )),
+        };
+    }
+    match env.procedures.iter().find(|p| p.name == value).cloned() {
+        Some(procedure) if procedure.args.is_empty() => {
+            Ok(Shape::Drawing(record_shape(env, &procedure, dimensions)))
         }
+        Some(_) => Err(format!(
+            "Error: Shape procedure '{value}' can't take arguments"
+        )),
+        None => Err(format!("Error: Unknown shape procedure '{value}'")),
+    }
+}
+
+/// Records what a procedure draws when the active turtle runs it from the
+/// canvas origin, heading up with its pen down. The turtle is put back where
+/// it was afterwards, so the procedure can't change the coordinate system.
+fn record_shape(
+    env: &mut Environment,
+    procedure: &Procedure,
+    (width, height): (u32, u32),
+) -> Vec<Primitive> {
+    let turtle = env.turtle();
+    let mut recorder = Turtle::new(turtle.name.clone(), 0.0, 0.0);
+    recorder.pen_down = true;
+    recorder.color = turtle.color;
+    recorder.stroke = turtle.stroke.clone();
+    recorder.fill_color = turtle.fill_color;
+    recorder.label_font = turtle.label_font.clone();
+
+    let (active, transform) = (env.active, env.transform);
+    let turtle = std::mem::replace(env.turtle_mut(), recorder);
+    env.transform = Transform {
+        origin: (0.0, 0.0),
+        ..transform
+    };
+    let mut display_list = DisplayList::new(width, height);
+    let recording_shape = std::mem::replace(&mut env.recording_shape, true);
+    execute_commands(env, &procedure.commands, &mut display_list);
+    env.recording_shape = recording_shape;
+    env.active = active;
+    env.transform = transform;
+    *env.turtle_mut() = turtle;
+    let shape: Vec<Primitive> = display_list
+        .items
+        .into_iter()
+        .map(|item| item.primitive)
+        .collect();
+    // Stamps are drawn as vector symbols, which can't hold a raster fill.
+    if shape
+        .iter()
+        .any(|primitive| matches!(primitive, Primitive::FloodFill { .. }))
+    {
+        exit_with_error(format!(
+            "Error: Shape procedure '{}' can't use FILL",
+            procedure.name
+        ));
+    }
+    shape
+}
+
+fn pen_move(
+    image: &mut dyn Renderer,
+    transform: &Transform,
+    turtle: &mut Turtle,
+    heading: i32,
+    length: f32,
+) {
+    let start = (turtle.pos_x, turtle.pos_y);
+    let (x, y) = transform.end_coordinates(start, heading, length);
+    if turtle.pen_down {
+        image.set_stroke(turtle.color, &turtle.stroke);
+        image.move_to(transform.to_canvas(start));
+        image.line_to(transform.to_canvas((x, y)));
+    } else {
+        image.move_to(transform.to_canvas((x, y)));
+    }
+    turtle.pos_x = x;
+    turtle.pos_y = y;
+    record_fill_point(turtle, transform);
+}
+
+fn record_fill_point(turtle: &mut Turtle, transform: &Transform) {
+    if let Some(fill) = &mut turtle.fill {
+        fill.points
+            .push(transform.to_canvas((turtle.pos_x, turtle.pos_y)));
     }
 }
 
-pub fn execute_commands(turtle: &mut Turtle, commands: &[Command], image: &mut Image) {
-    for command in commands {
-        command.execute(turtle, image);
+pub fn execute_commands(env: &mut Environment, commands: &[Statement], image: &mut dyn Renderer) {
+    let outer_line = env.line;
+    for statement in commands {
+        env.line = statement.line;
+        image.set_line(statement.line);
+        statement.command.execute(env, image);
     }
+    // Anything the calling command draws afterwards, such as the rest of an
+    // L-system that called a procedure, belongs to the caller's line.
+    env.line = outer_line;
+    image.set_line(outer_line);
 }
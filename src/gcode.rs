@@ -0,0 +1,125 @@
+use std::path::Path;
+
+use unsvg::Color;
+
+use crate::canvas::StrokeStyle;
+use crate::render::Plotter;
+
+/// Height of the pen above the paper while travelling, in millimetres.
+const PEN_UP_Z: f32 = 5.0;
+/// Height of the pen while drawing, in millimetres.
+const PEN_DOWN_Z: f32 = 0.0;
+
+/// How canvas pixels map onto the plotter's bed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GcodeOptions {
+    /// Millimetres per canvas pixel.
+    pub scale: f32,
+    /// Drawing speed in millimetres per minute.
+    pub feed_rate: f32,
+    /// Position of the bottom left corner of the canvas on the bed, in
+    /// millimetres.
+    pub origin: (f32, f32),
+}
+
+impl Default for GcodeOptions {
+    fn default() -> Self {
+        GcodeOptions {
+            scale: 0.25,
+            feed_rate: 1500.0,
+            origin: (0.0, 0.0),
+        }
+    }
+}
+
+/// Writes a drawing as G-code for a pen plotter. The pen is raised with a Z
+/// move whenever it has to travel between lines that don't join up.
+pub struct Gcode {
+    width: u32,
+    height: u32,
+    options: GcodeOptions,
+    lines: Vec<String>,
+    /// Where the pen actually is, in canvas pixels.
+    pen_position: Option<(f32, f32)>,
+    pen_down: bool,
+}
+
+impl Gcode {
+    pub fn new(width: u32, height: u32, options: GcodeOptions) -> Gcode {
+        Gcode {
+            width,
+            height,
+            options,
+            lines: vec![
+                "G21 ; millimetres".to_string(),
+                "G90 ; absolute positioning".to_string(),
+                format!("G0 Z{}", format_mm(PEN_UP_Z)),
+            ],
+            pen_position: None,
+            pen_down: false,
+        }
+    }
+
+    /// Bed coordinates of a canvas point. The canvas Y axis points down, while
+    /// the bed's points up.
+    fn to_bed(&self, (x, y): (f32, f32)) -> String {
+        let (origin_x, origin_y) = self.options.origin;
+        format!(
+            "X{} Y{}",
+            format_mm(origin_x + x * self.options.scale),
+            format_mm(origin_y + (self.height as f32 - y) * self.options.scale)
+        )
+    }
+
+    fn set_pen(&mut self, down: bool) {
+        if self.pen_down != down {
+            let z = if down { PEN_DOWN_Z } else { PEN_UP_Z };
+            self.lines.push(format!("G0 Z{}", format_mm(z)));
+            self.pen_down = down;
+        }
+    }
+}
+
+impl Plotter for Gcode {
+    const FORMAT: &'static str = "gcode";
+
+    fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn set_stroke(&mut self, _color: Color, _style: &StrokeStyle) {}
+
+    fn line(&mut self, from: (f32, f32), to: (f32, f32)) {
+        if self.pen_position != Some(from) {
+            self.set_pen(false);
+            let travel = format!("G0 {}", self.to_bed(from));
+            self.lines.push(travel);
+        }
+        self.set_pen(true);
+        let line = format!(
+            "G1 {} F{}",
+            self.to_bed(to),
+            format_mm(self.options.feed_rate)
+        );
+        self.lines.push(line);
+        self.pen_position = Some(to);
+    }
+
+    fn finish(&mut self, path: &Path) -> Result<(), String> {
+        self.set_pen(false);
+        self.lines.push("M2".to_string());
+        let mut gcode = self.lines.join("\n");
+        gcode.push('\n');
+        std::fs::write(path, gcode).map_err(|e| e.to_string())
+    }
+}
+
+/// Formats a length to a thousandth of a millimetre, without trailing zeros.
+fn format_mm(value: f32) -> String {
+    let formatted = format!("{value:.3}");
+    let trimmed = formatted.trim_end_matches('0').trim_end_matches('.');
+    match trimmed {
+        "-0" | "" => "0".to_string(),
+        _ => trimmed.to_string(),
+    }
+}
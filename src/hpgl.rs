@@ -0,0 +1,128 @@
+use std::path::Path;
+
+use unsvg::Color;
+
+use crate::canvas::StrokeStyle;
+use crate::render::Plotter;
+
+/// How a drawing maps onto an HPGL plotter.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HpglOptions {
+    /// Plotter units per canvas pixel. Plotters usually have 40 units per
+    /// millimetre.
+    pub scale: f32,
+    /// Pen numbers for palette indexes. Palette indexes without an entry use
+    /// pen `index + 1`.
+    pub pens: Vec<(usize, u32)>,
+}
+
+impl Default for HpglOptions {
+    fn default() -> Self {
+        HpglOptions {
+            scale: 10.0,
+            pens: Vec::new(),
+        }
+    }
+}
+
+/// Parses a pen mapping given on the command line as `INDEX=PEN`.
+pub fn parse_pen_mapping(value: &str) -> Result<(usize, u32), String> {
+    let error = || format!("Invalid pen mapping '{value}'. Expected INDEX=PEN");
+    let (index, pen) = value.split_once('=').ok_or_else(error)?;
+    Ok((
+        index.trim().parse().map_err(|_| error())?,
+        pen.trim().parse().map_err(|_| error())?,
+    ))
+}
+
+/// Writes a drawing as HPGL. Each stroke is drawn with the pen for its
+/// color's palette index, or the closest palette color for colors that
+/// aren't in the palette.
+pub struct Hpgl {
+    width: u32,
+    height: u32,
+    palette: Vec<Color>,
+    options: HpglOptions,
+    instructions: Vec<String>,
+    /// Where the pen actually is, in canvas pixels.
+    pen_position: Option<(f32, f32)>,
+    /// The pen for the current stroke color.
+    pen: u32,
+    selected_pen: Option<u32>,
+}
+
+impl Hpgl {
+    pub fn new(width: u32, height: u32, palette: Vec<Color>, options: HpglOptions) -> Hpgl {
+        Hpgl {
+            width,
+            height,
+            palette,
+            options,
+            instructions: vec!["IN".to_string()],
+            pen_position: None,
+            pen: 1,
+            selected_pen: None,
+        }
+    }
+
+    fn pen_for(&self, color: Color) -> u32 {
+        let distance = |c: &Color| {
+            let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+            d(c.red, color.red) + d(c.green, color.green) + d(c.blue, color.blue)
+        };
+        let index = (0..self.palette.len())
+            .min_by_key(|&i| distance(&self.palette[i]))
+            .unwrap_or(0);
+        self.options
+            .pens
+            .iter()
+            .rev()
+            .find(|(from, _)| *from == index)
+            .map_or(index as u32 + 1, |&(_, pen)| pen)
+    }
+
+    /// Plotter coordinates of a canvas point. The canvas Y axis points down,
+    /// while the plotter's points up.
+    fn to_plotter(&self, (x, y): (f32, f32)) -> String {
+        format!(
+            "{},{}",
+            (x * self.options.scale).round() as i32,
+            ((self.height as f32 - y) * self.options.scale).round() as i32
+        )
+    }
+}
+
+impl Plotter for Hpgl {
+    const FORMAT: &'static str = "hpgl";
+
+    fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn set_stroke(&mut self, color: Color, _style: &StrokeStyle) {
+        self.pen = self.pen_for(color);
+    }
+
+    fn line(&mut self, from: (f32, f32), to: (f32, f32)) {
+        if self.selected_pen != Some(self.pen) {
+            self.instructions.push(format!("SP{}", self.pen));
+            self.selected_pen = Some(self.pen);
+            self.pen_position = None;
+        }
+        if self.pen_position != Some(from) {
+            let travel = format!("PU{}", self.to_plotter(from));
+            self.instructions.push(travel);
+        }
+        let line = format!("PD{}", self.to_plotter(to));
+        self.instructions.push(line);
+        self.pen_position = Some(to);
+    }
+
+    fn finish(&mut self, path: &Path) -> Result<(), String> {
+        self.instructions.push("PU".to_string());
+        self.instructions.push("SP0".to_string());
+        let mut hpgl = self.instructions.join(";\n");
+        hpgl.push_str(";\n");
+        std::fs::write(path, hpgl).map_err(|e| e.to_string())
+    }
+}
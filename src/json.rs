@@ -0,0 +1,224 @@
+//! Exports a finished run as JSON, for tools that consume drawings
+//! programmatically. The document has the following shape:
+//!
+//! ```text
+//! {
+//!   "version": 1,
+//!   "width": 200,
+//!   "height": 200,
+//!   "background": "#000000",
+//!   "primitives": [
+//!     {"type": "line", "line": 3, "from": [100, 100], "to": [100, 50],
+//!      "color": "#ffffff", "width": 1, "lineCap": "butt", "dash": [],
+//!      "opacity": 1, "gradient": {"to": "#ff0000", "span": "segment"}},
+//!     {"type": "arc", "line": 4, "center": [100, 50], "heading": 0,
+//!      "angle": 90, "radius": 20, "color": ..., "width": ..., "lineCap": ...,
+//!      "dash": ..., "opacity": ..., "gradient": null},
+//!     {"type": "text", "line": 5, "position": [100, 50], "heading": 90,
+//!      "text": "Hello", "fontFamily": "sans-serif", "fontHeight": 12, "color": ...},
+//!     {"type": "fill", "line": 6, "points": [[100, 50], ...], "color": ...},
+//!     {"type": "floodFill", "line": 7, "point": [100, 50], "color": ...},
+//!     {"type": "stamp", "line": 8, "position": [100, 50], "heading": 90,
+//!      "shape": [{"type": "fill", "points": [[0, -8], ...], "color": ...}]}
+//!   ],
+//!   "turtles": [
+//!     {"name": "0", "active": true, "x": 100, "y": 50, "heading": 90,
+//!      "penDown": true, "shown": false, "color": "#ffffff"}
+//!   ],
+//!   "variables": {"length": "50"}
+//! }
+//! ```
+//!
+//! Primitives are listed in painting order and their coordinates are canvas
+//! pixels. `line` is the source line of the command that drew them. A
+//! stamp's shape is made of primitives relative to a turtle at the origin
+//! heading up, turned through `heading` and moved to `position`. Turtle
+//! positions are in the program's own coordinate system, as returned by
+//! `XCOR` and `YCOR`. `version` is increased whenever the schema changes in a
+//! way that could break existing readers.
+
+use std::path::Path;
+
+use unsvg::Color;
+
+use crate::canvas::{hex_color, StrokeStyle};
+use crate::display::{DisplayList, Primitive};
+use crate::render::Renderer;
+use crate::utils::Environment;
+
+pub const JSON_VERSION: u32 = 1;
+
+pub fn save_json(path: &Path, display_list: &DisplayList, env: &Environment) -> Result<(), String> {
+    std::fs::write(path, to_json(display_list, env)).map_err(|e| e.to_string())
+}
+
+pub fn to_json(display_list: &DisplayList, env: &Environment) -> String {
+    let (width, height) = display_list.dimensions();
+    let primitives: Vec<String> = display_list
+        .items
+        .iter()
+        .map(|item| format!("    {}", object(&item.primitive, Some(item.line))))
+        .collect();
+
+    let turtles: Vec<String> = env
+        .turtles
+        .iter()
+        .enumerate()
+        .map(|(i, turtle)| {
+            format!(
+                r#"    {{"name": {}, "active": {}, "x": {}, "y": {}, "heading": {}, "penDown": {}, "shown": {}, "color": {}}}"#,
+                string(&turtle.name),
+                i == env.active,
+                turtle.pos_x,
+                turtle.pos_y,
+                turtle.heading,
+                turtle.pen_down,
+                turtle.shown,
+                hex(turtle.color)
+            )
+        })
+        .collect();
+
+    // Later bindings shadow earlier ones with the same name.
+    let mut variables: Vec<(&str, &str)> = Vec::new();
+    for variable in &env.variables {
+        variables.retain(|(name, _)| *name != variable.name);
+        variables.push((&variable.name, &variable.value));
+    }
+    let variables: Vec<String> = variables
+        .iter()
+        .map(|(name, value)| format!("    {}: {}", string(name), string(value)))
+        .collect();
+
+    format!(
+        "{{\n  \"version\": {JSON_VERSION},\n  \"width\": {width},\n  \"height\": {height},\n  \"background\": {},\n  \"primitives\": {},\n  \"turtles\": {},\n  \"variables\": {}\n}}\n",
+        hex(env.background),
+        array(&primitives, '[', ']'),
+        array(&turtles, '[', ']'),
+        array(&variables, '{', '}')
+    )
+}
+
+fn array(entries: &[String], open: char, close: char) -> String {
+    if entries.is_empty() {
+        format!("{open}{close}")
+    } else {
+        format!("{open}\n{}\n  {close}", entries.join(",\n"))
+    }
+}
+
+/// A primitive as a JSON object. Primitives inside a stamp's shape have no
+/// source line of their own.
+fn object(primitive: &Primitive, line: Option<usize>) -> String {
+    let line = line.map_or(String::new(), |line| format!(r#", "line": {line}"#));
+    let fields = match primitive {
+        Primitive::Line {
+            from,
+            to,
+            color,
+            style,
+        } => format!(
+            r#""type": "line"{line}, "from": {}, "to": {}, {}"#,
+            point(*from),
+            point(*to),
+            stroke(*color, style)
+        ),
+        Primitive::Arc {
+            center,
+            heading,
+            angle,
+            radius,
+            color,
+            style,
+        } => format!(
+            r#""type": "arc"{line}, "center": {}, "heading": {heading}, "angle": {angle}, "radius": {radius}, {}"#,
+            point(*center),
+            stroke(*color, style)
+        ),
+        Primitive::Text {
+            position,
+            heading,
+            text,
+            font,
+            color,
+        } => format!(
+            r#""type": "text"{line}, "position": {}, "heading": {heading}, "text": {}, "fontFamily": {}, "fontHeight": {}, "color": {}"#,
+            point(*position),
+            string(text),
+            string(&font.family),
+            font.height,
+            hex(*color)
+        ),
+        Primitive::Fill { points, color } => format!(
+            r#""type": "fill"{line}, "points": [{}], "color": {}"#,
+            points
+                .iter()
+                .map(|&p| point(p))
+                .collect::<Vec<_>>()
+                .join(", "),
+            hex(*color)
+        ),
+        Primitive::FloodFill { point: p, color } => format!(
+            r#""type": "floodFill"{line}, "point": {}, "color": {}"#,
+            point(*p),
+            hex(*color)
+        ),
+        Primitive::Stamp {
+            shape,
+            position,
+            heading,
+        } => format!(
+            r#""type": "stamp"{line}, "position": {}, "heading": {heading}, "shape": [{}]"#,
+            point(*position),
+            shape
+                .iter()
+                .map(|primitive| object(primitive, None))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    };
+    format!("{{{fields}}}")
+}
+
+fn point((x, y): (f32, f32)) -> String {
+    format!("[{x}, {y}]")
+}
+
+fn stroke(color: Color, style: &StrokeStyle) -> String {
+    let dash: Vec<String> = style.dash.iter().map(|d| d.to_string()).collect();
+    let gradient = match style.gradient {
+        Some(gradient) => format!(
+            r#"{{"to": {}, "span": "{}"}}"#,
+            hex(gradient.to),
+            gradient.span.as_str()
+        ),
+        None => "null".to_string(),
+    };
+    format!(
+        r#""color": {}, "width": {}, "lineCap": "{}", "dash": [{}], "opacity": {}, "gradient": {gradient}"#,
+        hex(color),
+        style.width,
+        style.line_cap.as_str(),
+        dash.join(", "),
+        style.opacity
+    )
+}
+
+fn hex(color: Color) -> String {
+    string(&hex_color(color))
+}
+
+fn string(value: &str) -> String {
+    let mut escaped = String::from('"');
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
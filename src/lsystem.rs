@@ -0,0 +1,106 @@
+use std::str::FromStr;
+
+/// The longest string an L-system may expand to before it is rejected.
+pub const MAX_EXPANSION_LENGTH: usize = 1_000_000;
+
+/// What the turtle does when it reads a symbol of an expanded L-system.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    Forward,
+    Move,
+    Left,
+    Right,
+    TurnAround,
+    Push,
+    Pop,
+    Ignore,
+    Procedure(String),
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    /// Any name that isn't a built-in action is treated as a procedure to call.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "FORWARD" => Ok(Action::Forward),
+            "MOVE" => Ok(Action::Move),
+            "LEFT" => Ok(Action::Left),
+            "RIGHT" => Ok(Action::Right),
+            "TURNAROUND" => Ok(Action::TurnAround),
+            "PUSH" => Ok(Action::Push),
+            "POP" => Ok(Action::Pop),
+            "IGNORE" => Ok(Action::Ignore),
+            "" => Err("Error: L-system action must not be empty".to_string()),
+            _ => Ok(Action::Procedure(s.to_string())),
+        }
+    }
+}
+
+/// The symbols understood by every L-system, following the usual turtle
+/// interpretation: `F`/`G` draw, `f` moves, `+`/`-` turn left/right, `|` turns
+/// around and `[`/`]` save and restore the turtle.
+pub fn default_actions() -> Vec<(char, Action)> {
+    vec![
+        ('F', Action::Forward),
+        ('G', Action::Forward),
+        ('f', Action::Move),
+        ('+', Action::Left),
+        ('-', Action::Right),
+        ('|', Action::TurnAround),
+        ('[', Action::Push),
+        (']', Action::Pop),
+    ]
+}
+
+/// Parses rewrite rules given as a list of alternating symbols and
+/// replacements, such as `[F F+F-F X F[+X]]`. A replacement may also be
+/// quoted, as in `[X "F[+X]]`, which reads the same.
+pub fn parse_rules(value: &str) -> Result<Vec<(char, String)>, String> {
+    let error = || {
+        format!(
+            "Error: Invalid L-system rules '{value}'. Expected a list of symbols and replacements"
+        )
+    };
+    let words: Vec<&str> = value
+        .strip_prefix('[')
+        .and_then(|v| v.strip_suffix(']'))
+        .ok_or_else(error)?
+        .split_whitespace()
+        .collect();
+    if words.len() % 2 == 1 {
+        return Err(error());
+    }
+    words
+        .chunks(2)
+        .map(|pair| {
+            let mut symbol = pair[0].chars();
+            match (symbol.next(), symbol.next()) {
+                (Some(symbol), None) => Ok((symbol, pair[1].to_string())),
+                _ => Err(error()),
+            }
+        })
+        .collect()
+}
+
+/// Rewrites `axiom` with `rules` the given number of times, failing if the
+/// result would be longer than `MAX_EXPANSION_LENGTH` symbols.
+pub fn expand(axiom: &str, rules: &[(char, String)], iterations: usize) -> Result<String, String> {
+    let mut current = axiom.to_string();
+    for _ in 0..iterations {
+        let mut next = String::new();
+        for symbol in current.chars() {
+            match rules.iter().find(|(from, _)| *from == symbol) {
+                Some((_, replacement)) => next.push_str(replacement),
+                None => next.push(symbol),
+            }
+            if next.len() > MAX_EXPANSION_LENGTH {
+                return Err(format!(
+                    "Error: L-system expands to more than {MAX_EXPANSION_LENGTH} symbols"
+                ));
+            }
+        }
+        current = next;
+    }
+    Ok(current)
+}
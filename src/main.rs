@@ -1,19 +1,121 @@
+use canvas::{Animation, Framing, SvgOptions, MAX_PRECISION};
 use clap::Parser;
-use utils::start;
+use color::parse_color;
+use gcode::GcodeOptions;
+use hpgl::{parse_pen_mapping, HpglOptions};
+use preview::{PreviewOptions, PreviewStyle};
+use unsvg::{Color, COLORS};
+use utils::{start_with_options, OutputOptions};
+mod canvas;
+mod color;
+mod display;
+mod eps;
 mod execute;
+mod gcode;
+mod hpgl;
+mod json;
+mod lsystem;
+mod optimize;
+mod page;
 mod parse;
-pub mod tests;
+mod pdf;
+mod preview;
+mod render;
+mod shape;
+#[cfg(test)]
+mod tests;
+mod transform;
 mod utils;
 
 #[derive(Parser)]
 struct Args {
     file_path: std::path::PathBuf,
 
+    /// Where to save the image, or - to only preview it in the terminal
     image_path: std::path::PathBuf,
 
     height: u32,
 
     width: u32,
+
+    /// Scale the drawing to fill the image, leaving a margin of --padding pixels
+    #[arg(long, conflicts_with = "crop")]
+    fit: bool,
+
+    /// Size the image to the drawing, plus --padding pixels on each side
+    #[arg(long)]
+    crop: bool,
+
+    /// Space left around the drawing by --fit and --crop
+    #[arg(long, default_value_t = 10.0)]
+    padding: f32,
+
+    /// Join connected lines of the same color and style into polylines in svg output
+    #[arg(long)]
+    compact_svg: bool,
+
+    /// Group svg elements into a layer for each color
+    #[arg(long)]
+    svg_layers: bool,
+
+    /// Decimal places kept in svg coordinates, up to 6
+    #[arg(long, default_value_t = 2, value_parser = clap::value_parser!(u8).range(0..=MAX_PRECISION as i64))]
+    svg_precision: u8,
+
+    /// Millimetres per pixel in gcode output
+    #[arg(long, default_value_t = 0.25)]
+    gcode_scale: f32,
+
+    /// Drawing speed in millimetres per minute in gcode output
+    #[arg(long, default_value_t = 1500.0)]
+    gcode_feed_rate: f32,
+
+    /// Position of the bottom left corner of the drawing on the plotter bed, in millimetres
+    #[arg(long, num_args = 2, value_names = ["X", "Y"], default_values_t = [0.0, 0.0])]
+    gcode_origin: Vec<f32>,
+
+    /// Plotter units per pixel in hpgl output
+    #[arg(long, default_value_t = 10.0)]
+    hpgl_scale: f32,
+
+    /// Draw strokes of a palette index with a plotter pen in hpgl output, such as 4=2.
+    /// Indexes without a mapping use pen INDEX + 1
+    #[arg(long, value_name = "INDEX=PEN", value_parser = parse_pen_mapping)]
+    hpgl_pen: Vec<(usize, u32)>,
+
+    /// Join and reorder strokes to reduce pen-up travel in gcode and hpgl output
+    #[arg(long)]
+    optimize_paths: bool,
+
+    /// Animate the drawing being built up: svg output reveals each step in turn
+    /// and png output is written as numbered frames. Gif output is always animated
+    #[arg(long)]
+    animate: bool,
+
+    /// Frames shown for each drawing step in animated output
+    #[arg(long, default_value_t = 1.0)]
+    frames_per_step: f32,
+
+    /// Length of animated output in seconds
+    #[arg(long, default_value_t = 5.0)]
+    duration: f32,
+
+    /// Canvas color until the program sets its own: a palette index, [R G B] list,
+    /// hex color or color name
+    #[arg(long, default_value = "0", value_parser = |value: &str| parse_color(value, &COLORS))]
+    background: Color,
+
+    /// Also print the drawing to the terminal
+    #[arg(long)]
+    preview: bool,
+
+    /// Characters the terminal preview is drawn with: braille or blocks
+    #[arg(long, default_value = "braille")]
+    preview_style: PreviewStyle,
+
+    /// Width of the terminal preview in characters. Defaults to $COLUMNS, or 80
+    #[arg(long)]
+    preview_width: Option<usize>,
 }
 
 fn main() -> Result<(), ()> {
@@ -24,7 +126,65 @@ fn main() -> Result<(), ()> {
     let height = args.height;
     let width = args.width;
 
-    let _ = start(file_path, image_path, width, height);
+    let framing = if args.fit {
+        Framing::Fit {
+            margin: args.padding,
+        }
+    } else if args.crop {
+        Framing::Crop {
+            padding: args.padding,
+        }
+    } else {
+        Framing::Canvas
+    };
+
+    let animation = Animation {
+        frames_per_step: args.frames_per_step,
+        duration: args.duration,
+    };
+    let animate = args.animate
+        || image_path
+            .extension()
+            .is_some_and(|extension| extension == "gif");
+
+    let preview_options = PreviewOptions {
+        style: args.preview_style,
+        columns: args.preview_width.unwrap_or_else(|| {
+            std::env::var("COLUMNS")
+                .ok()
+                .and_then(|columns| columns.parse().ok())
+                .unwrap_or(80)
+        }),
+    };
+    let preview = args.preview || image_path.as_os_str() == "-";
+
+    let _ = start_with_options(
+        file_path,
+        image_path,
+        width,
+        height,
+        OutputOptions {
+            framing,
+            svg: SvgOptions {
+                compact: args.compact_svg,
+                color_layers: args.svg_layers,
+                precision: usize::from(args.svg_precision),
+            },
+            gcode: GcodeOptions {
+                scale: args.gcode_scale,
+                feed_rate: args.gcode_feed_rate,
+                origin: (args.gcode_origin[0], args.gcode_origin[1]),
+            },
+            hpgl: HpglOptions {
+                scale: args.hpgl_scale,
+                pens: args.hpgl_pen,
+            },
+            optimize_paths: args.optimize_paths,
+            animation: animate.then_some(animation),
+            preview: preview.then_some(preview_options),
+            background: args.background,
+        },
+    );
 
     Ok(())
 }
@@ -0,0 +1,226 @@
+use std::{collections::VecDeque, fmt};
+
+use unsvg::Color;
+
+use crate::canvas::StrokeStyle;
+use crate::display::{DisplayList, Item, Primitive};
+use crate::render::arc_points;
+
+/// Points closer than this, in pixels, are treated as the same point.
+const TOLERANCE: f32 = 1e-3;
+
+/// Pen-up travel of a drawing before and after optimization, in pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TravelReport {
+    pub before: f32,
+    pub after: f32,
+}
+
+impl fmt::Display for TravelReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Pen-up travel reduced from {:.1} to {:.1} pixels",
+            self.before, self.after
+        )
+    }
+}
+
+/// A run of connected strokes drawn with the same pen.
+#[derive(Debug, Clone)]
+struct Polyline {
+    line: usize,
+    color: Color,
+    style: StrokeStyle,
+    points: Vec<(f32, f32)>,
+}
+
+impl Polyline {
+    fn start(&self) -> (f32, f32) {
+        self.points[0]
+    }
+
+    fn end(&self) -> (f32, f32) {
+        self.points[self.points.len() - 1]
+    }
+
+    fn reverse(&mut self) {
+        self.points.reverse();
+    }
+
+    /// Continues this polyline with `next`, which must start where this one
+    /// ends.
+    fn extend(&mut self, next: Polyline) {
+        self.points.extend(next.points.into_iter().skip(1));
+    }
+}
+
+/// Rewrites the strokes of a display list to draw faster on a plotter:
+/// strokes are grouped by color so each pen is only picked up once, strokes
+/// that share endpoints are joined, collinear segments are merged and the
+/// remaining pen-up moves are ordered nearest first. Arcs become lines, and
+/// everything other than strokes is kept after them.
+pub fn optimize_paths(display_list: &mut DisplayList) -> TravelReport {
+    let mut polylines: Vec<Polyline> = Vec::new();
+    let mut others: Vec<Item> = Vec::new();
+    for item in display_list.items.drain(..) {
+        let (points, color, style) = match item.primitive {
+            Primitive::Line {
+                from,
+                to,
+                color,
+                style,
+            } => (vec![from, to], color, style),
+            Primitive::Arc {
+                center,
+                heading,
+                angle,
+                radius,
+                color,
+                style,
+            } => (arc_points(center, heading, angle, radius), color, style),
+            _ => {
+                others.push(item);
+                continue;
+            }
+        };
+        let polyline = Polyline {
+            line: item.line,
+            color,
+            style,
+            points,
+        };
+        match polylines.last_mut() {
+            Some(last)
+                if last.color == polyline.color
+                    && last.style == polyline.style
+                    && same_point(last.end(), polyline.start()) =>
+            {
+                last.extend(polyline)
+            }
+            _ => polylines.push(polyline),
+        }
+    }
+    let before = travel(&polylines);
+
+    // Group by color, in the order each color is first used.
+    let mut groups: Vec<Vec<Polyline>> = Vec::new();
+    for polyline in polylines {
+        match groups.iter_mut().find(|g| g[0].color == polyline.color) {
+            Some(group) => group.push(polyline),
+            None => groups.push(vec![polyline]),
+        }
+    }
+
+    let mut optimized: Vec<Polyline> = Vec::new();
+    for group in groups {
+        let mut chains = join(group);
+        for chain in chains.iter_mut() {
+            merge_collinear(&mut chain.points);
+        }
+        let position = optimized
+            .last()
+            .map_or_else(|| chains[0].start(), Polyline::end);
+        optimized.extend(nearest_first(chains, position));
+    }
+    let after = travel(&optimized);
+
+    for polyline in optimized {
+        for segment in polyline.points.windows(2) {
+            display_list.items.push(Item {
+                line: polyline.line,
+                primitive: Primitive::Line {
+                    from: segment[0],
+                    to: segment[1],
+                    color: polyline.color,
+                    style: polyline.style.clone(),
+                },
+            });
+        }
+    }
+    display_list.items.extend(others);
+    TravelReport { before, after }
+}
+
+fn same_point(a: (f32, f32), b: (f32, f32)) -> bool {
+    distance(a, b) <= TOLERANCE
+}
+
+fn distance(a: (f32, f32), b: (f32, f32)) -> f32 {
+    (a.0 - b.0).hypot(a.1 - b.1)
+}
+
+/// Total distance travelled with the pen up between polylines.
+fn travel(polylines: &[Polyline]) -> f32 {
+    polylines
+        .windows(2)
+        .map(|pair| distance(pair[0].end(), pair[1].start()))
+        .sum()
+}
+
+/// Joins polylines that share an endpoint into longer chains.
+fn join(polylines: Vec<Polyline>) -> Vec<Polyline> {
+    let mut remaining = VecDeque::from(polylines);
+    let mut chains = Vec::new();
+    while let Some(mut chain) = remaining.pop_front() {
+        // Grow the chain from its end, then from its start by reversing it.
+        for _ in 0..2 {
+            while let Some(i) = remaining.iter().position(|p| {
+                p.style == chain.style
+                    && (same_point(p.start(), chain.end()) || same_point(p.end(), chain.end()))
+            }) {
+                let mut next = remaining.remove(i).unwrap();
+                if !same_point(next.start(), chain.end()) {
+                    next.reverse();
+                }
+                chain.extend(next);
+            }
+            chain.reverse();
+        }
+        chains.push(chain);
+    }
+    chains
+}
+
+/// Removes points in the middle of straight runs.
+fn merge_collinear(points: &mut Vec<(f32, f32)>) {
+    let mut merged: Vec<(f32, f32)> = Vec::with_capacity(points.len());
+    for &point in points.iter() {
+        if merged.last().is_some_and(|&last| same_point(last, point)) {
+            continue;
+        }
+        if let [.., a, b] = merged[..] {
+            let first = (b.0 - a.0, b.1 - a.1);
+            let second = (point.0 - b.0, point.1 - b.1);
+            let cross = first.0 * second.1 - first.1 * second.0;
+            let dot = first.0 * second.0 + first.1 * second.1;
+            if cross.abs() <= TOLERANCE * distance(a, point) && dot > 0.0 {
+                merged.pop();
+            }
+        }
+        merged.push(point);
+    }
+    *points = merged;
+}
+
+/// Orders polylines so each starts at the closest remaining endpoint to where
+/// the last one finished, reversing them where that's shorter.
+fn nearest_first(polylines: Vec<Polyline>, mut position: (f32, f32)) -> Vec<Polyline> {
+    let mut remaining = polylines;
+    let mut ordered = Vec::with_capacity(remaining.len());
+    while !remaining.is_empty() {
+        let closest = |p: &Polyline| distance(position, p.start()).min(distance(position, p.end()));
+        let (i, _) = remaining
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| closest(a).total_cmp(&closest(b)))
+            .unwrap();
+        let mut next = remaining.swap_remove(i);
+        if distance(position, next.end()) < distance(position, next.start()) {
+            next.reverse();
+        }
+        position = next.end();
+        ordered.push(next);
+    }
+    ordered
+}
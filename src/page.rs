@@ -0,0 +1,260 @@
+use std::path::Path;
+
+use unsvg::{Color, COLORS};
+
+use crate::canvas::{format_number, Font, StrokeStyle};
+use crate::eps::eps_document;
+use crate::pdf::pdf_document;
+use crate::render::{arc_points, warn_stroke_effects_unsupported, Renderer};
+
+/// The page description formats a [`Page`] can be written as. Both draw with
+/// the same PostScript imaging model and differ only in how operators are
+/// spelled and how the page is wrapped.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PageFormat {
+    Pdf,
+    Eps,
+}
+
+/// The operators a format draws paths with.
+struct Operators {
+    stroke_color: &'static str,
+    fill_color: &'static str,
+    line_width: &'static str,
+    line_cap: &'static str,
+    dash: &'static str,
+    new_path: &'static str,
+    move_to: &'static str,
+    line_to: &'static str,
+    stroke: &'static str,
+    close_and_fill: &'static str,
+}
+
+const PDF_OPERATORS: Operators = Operators {
+    stroke_color: "RG",
+    fill_color: "rg",
+    line_width: "w",
+    line_cap: "J",
+    dash: "d",
+    new_path: "",
+    move_to: "m",
+    line_to: "l",
+    stroke: "S",
+    close_and_fill: "h f",
+};
+
+const POSTSCRIPT_OPERATORS: Operators = Operators {
+    stroke_color: "setrgbcolor",
+    fill_color: "setrgbcolor",
+    line_width: "setlinewidth",
+    line_cap: "setlinecap",
+    dash: "setdash",
+    new_path: "newpath ",
+    move_to: "moveto",
+    line_to: "lineto",
+    stroke: "stroke",
+    close_and_fill: "closepath fill",
+};
+
+impl PageFormat {
+    fn operators(self) -> &'static Operators {
+        match self {
+            PageFormat::Pdf => &PDF_OPERATORS,
+            PageFormat::Eps => &POSTSCRIPT_OPERATORS,
+        }
+    }
+}
+
+/// Writes a drawing as a single vector page, one point per canvas pixel.
+/// Labels are set in Helvetica, whatever their font family.
+pub struct Page {
+    format: PageFormat,
+    width: u32,
+    height: u32,
+    content: Vec<String>,
+    position: (f32, f32),
+    stroke: (Color, StrokeStyle),
+    has_flood_fills: bool,
+    has_stroke_effects: bool,
+}
+
+impl Page {
+    pub fn new(format: PageFormat, width: u32, height: u32, background: Color) -> Page {
+        let mut page = Page {
+            format,
+            width,
+            height,
+            content: Vec::new(),
+            position: (0.0, 0.0),
+            stroke: (COLORS[7], StrokeStyle::default()),
+            has_flood_fills: false,
+            has_stroke_effects: false,
+        };
+        let corners = [
+            (0.0, 0.0),
+            (width as f32, 0.0),
+            (width as f32, height as f32),
+            (0.0, height as f32),
+        ];
+        page.fill(0, &corners, background);
+        page
+    }
+
+    /// Page coordinates of a canvas point. The canvas Y axis points down,
+    /// while the page's points up.
+    fn to_page(&self, (x, y): (f32, f32)) -> String {
+        format!(
+            "{} {}",
+            format_number(x, 2),
+            format_number(self.height as f32 - y, 2)
+        )
+    }
+
+    /// Operators tracing a path through `points`.
+    fn path(&self, points: &[(f32, f32)]) -> Vec<String> {
+        let operators = self.format.operators();
+        let mut path = vec![format!(
+            "{}{} {}",
+            operators.new_path,
+            self.to_page(points[0]),
+            operators.move_to
+        )];
+        for &point in &points[1..] {
+            path.push(format!("{} {}", self.to_page(point), operators.line_to));
+        }
+        path
+    }
+
+    fn stroke_path(&mut self, points: &[(f32, f32)]) {
+        let operators = self.format.operators();
+        let (color, style) = &self.stroke;
+        let dash: Vec<String> = style.dash.iter().map(|&d| format_number(d, 2)).collect();
+        let mut content = vec![
+            format!("{} {}", rgb(*color), operators.stroke_color),
+            format!("{} {}", format_number(style.width, 2), operators.line_width),
+            format!(
+                "{} {}",
+                style.line_cap.postscript_code(),
+                operators.line_cap
+            ),
+            format!("[{}] 0 {}", dash.join(" "), operators.dash),
+        ];
+        content.extend(self.path(points));
+        content.push(operators.stroke.to_string());
+        self.content.push(content.join(" "));
+    }
+}
+
+impl Renderer for Page {
+    fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn set_stroke(&mut self, color: Color, style: &StrokeStyle) {
+        self.has_stroke_effects |= style.opacity != 1.0 || style.gradient.is_some();
+        self.stroke = (color, style.clone());
+    }
+
+    fn move_to(&mut self, point: (f32, f32)) {
+        self.position = point;
+    }
+
+    fn line_to(&mut self, point: (f32, f32)) {
+        self.stroke_path(&[self.position, point]);
+        self.position = point;
+    }
+
+    fn arc(&mut self, center: (f32, f32), heading: f32, angle: f32, radius: f32) {
+        self.stroke_path(&arc_points(center, heading, angle, radius));
+    }
+
+    fn text(&mut self, position: (f32, f32), heading: i32, text: &str, font: &Font, color: Color) {
+        // Text reads towards 90 degrees (right) when it isn't rotated, and
+        // page angles increase counterclockwise.
+        let content = match self.format {
+            PageFormat::Pdf => {
+                let radians = (90.0 - heading as f32).to_radians();
+                let (sin, cos) = (radians.sin(), radians.cos());
+                format!(
+                    "BT {} rg /F1 {} Tf {} {} {} {} {} Tm ({}) Tj ET",
+                    rgb(color),
+                    format_number(font.height, 2),
+                    format_number(cos, 4),
+                    format_number(sin, 4),
+                    format_number(-sin, 4),
+                    format_number(cos, 4),
+                    self.to_page(position),
+                    escape_string(text)
+                )
+            }
+            PageFormat::Eps => {
+                let rotation = (90 - heading).rem_euclid(360);
+                format!(
+                    "gsave {} setrgbcolor /Helvetica findfont {} scalefont setfont {} translate {rotation} rotate 0 0 moveto ({}) show grestore",
+                    rgb(color),
+                    format_number(font.height, 2),
+                    self.to_page(position),
+                    escape_string(text)
+                )
+            }
+        };
+        self.content.push(content);
+    }
+
+    fn layer(&self) -> usize {
+        self.content.len()
+    }
+
+    fn fill(&mut self, layer: usize, points: &[(f32, f32)], color: Color) {
+        if points.len() < 3 {
+            return;
+        }
+        let operators = self.format.operators();
+        let mut content = vec![format!("{} {}", rgb(color), operators.fill_color)];
+        content.extend(self.path(points));
+        content.push(operators.close_and_fill.to_string());
+        let layer = layer.min(self.content.len());
+        self.content.insert(layer, content.join(" "));
+    }
+
+    fn flood_fill(&mut self, _: (f32, f32), _: Color) {
+        self.has_flood_fills = true;
+    }
+
+    fn finish(&mut self, path: &Path) -> Result<(), String> {
+        if self.has_flood_fills {
+            return Err("FILL is only supported for png and gif output".to_string());
+        }
+        if self.has_stroke_effects {
+            warn_stroke_effects_unsupported();
+        }
+        let content = self.content.join("\n");
+        let document = match self.format {
+            PageFormat::Pdf => pdf_document(self.width, self.height, &content),
+            PageFormat::Eps => eps_document(self.width, self.height, &content),
+        };
+        std::fs::write(path, document).map_err(|e| e.to_string())
+    }
+}
+
+/// A color as PDF or PostScript color operands.
+fn rgb(color: Color) -> String {
+    format!(
+        "{} {} {}",
+        format_number(color.red as f32 / 255.0, 3),
+        format_number(color.green as f32 / 255.0, 3),
+        format_number(color.blue as f32 / 255.0, 3)
+    )
+}
+
+/// Escapes text for a PDF or PostScript string. Characters outside the
+/// standard fonts' ASCII range are replaced with `?`.
+fn escape_string(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '(' | ')' | '\\' => format!("\\{c}"),
+            ' '..='~' => c.to_string(),
+            _ => "?".to_string(),
+        })
+        .collect()
+}
@@ -1,10 +1,10 @@
 use std::{collections::VecDeque, fs, path::Path, str::SplitWhitespace};
 
-use crate::utils::{exit_with_error, is_valid_value, Command, Expression};
+use crate::utils::{exit_with_error, is_valid_value, Command, Expression, Statement};
 
-pub fn parse_commands(file_path: &Path) -> Result<Vec<Command>, ()> {
+pub fn parse_commands(file_path: &Path) -> Result<Vec<Statement>, ()> {
     let file = fs::read_to_string(file_path);
-    let mut commands: Vec<Command> = Vec::new();
+    let mut commands: Vec<Statement> = Vec::new();
     match file {
         Ok(lines_string) => {
             let lines: Vec<String> = lines_string.lines().map(|line| line.to_string()).collect();
@@ -27,10 +27,13 @@ struct ExpectedToken {
 fn parse_lines(
     lines: Vec<String>,
     mut i: usize,
-    commands: &mut Vec<Command>,
+    statements: &mut Vec<Statement>,
     expected_token: &mut ExpectedToken,
 ) -> usize {
     while let Some(line) = lines.get(i) {
+        // A line parses to at most one command, tagged with its line number.
+        let line_number = i + 1;
+        let mut commands: Vec<Command> = Vec::new();
         let trimmed_line = line.trim();
         if trimmed_line.starts_with("//") {
             i += 1;
@@ -49,6 +52,26 @@ fn parse_lines(
                     get_args(cmd, Some(0), &mut words, &mut args);
                     commands.push(Command::PenDown);
                 }
+                "SHOWTURTLE" => {
+                    get_args(cmd, Some(0), &mut words, &mut args);
+                    commands.push(Command::ShowTurtle);
+                }
+                "HIDETURTLE" => {
+                    get_args(cmd, Some(0), &mut words, &mut args);
+                    commands.push(Command::HideTurtle);
+                }
+                "SETBACKGROUND" => {
+                    get_args(cmd, Some(1), &mut words, &mut args);
+                    commands.push(Command::SetBackground(args[0].clone()));
+                }
+                "STAMP" => {
+                    get_args(cmd, Some(0), &mut words, &mut args);
+                    commands.push(Command::Stamp);
+                }
+                "SETSHAPE" => {
+                    get_args(cmd, Some(1), &mut words, &mut args);
+                    commands.push(Command::SetShape(args[0].clone()));
+                }
                 "FORWARD" => {
                     get_args(cmd, Some(1), &mut words, &mut args);
                     commands.push(Command::Forward(args[0].clone()));
@@ -69,14 +92,90 @@ fn parse_lines(
                     get_args(cmd, Some(1), &mut words, &mut args);
                     commands.push(Command::SetPenColor(args[0].clone()));
                 }
+                "SETPALETTE" => {
+                    get_args(cmd, Some(2), &mut words, &mut args);
+                    commands.push(Command::SetPalette((args[0].clone(), args[1].clone())));
+                }
+                "SETPENSIZE" => {
+                    get_args(cmd, Some(1), &mut words, &mut args);
+                    commands.push(Command::SetPenSize(args[0].clone()));
+                }
+                "SETLINECAP" => {
+                    get_args(cmd, Some(1), &mut words, &mut args);
+                    commands.push(Command::SetLineCap(args[0].clone()));
+                }
+                "SETDASH" => {
+                    get_args(cmd, Some(1), &mut words, &mut args);
+                    commands.push(Command::SetDash(args[0].clone()));
+                }
+                "SETPENALPHA" => {
+                    get_args(cmd, Some(1), &mut words, &mut args);
+                    commands.push(Command::SetPenAlpha(args[0].clone()));
+                }
+                "SETPENGRADIENT" => {
+                    get_args(cmd, Some(2), &mut words, &mut args);
+                    commands.push(Command::SetPenGradient((args[0].clone(), args[1].clone())));
+                }
+                "CLEARPENGRADIENT" => {
+                    get_args(cmd, Some(0), &mut words, &mut args);
+                    commands.push(Command::ClearPenGradient);
+                }
+                "SETFILLCOLOR" => {
+                    get_args(cmd, Some(1), &mut words, &mut args);
+                    commands.push(Command::SetFillColor(args[0].clone()));
+                }
+                "BEGINFILL" => {
+                    get_args(cmd, Some(0), &mut words, &mut args);
+                    commands.push(Command::BeginFill);
+                }
+                "ENDFILL" => {
+                    get_args(cmd, Some(0), &mut words, &mut args);
+                    commands.push(Command::EndFill);
+                }
+                "FILL" => {
+                    get_args(cmd, Some(0), &mut words, &mut args);
+                    commands.push(Command::Fill);
+                }
                 "TURN" => {
                     get_args(cmd, Some(1), &mut words, &mut args);
                     commands.push(Command::Turn(args[0].clone()));
                 }
+                "ARC" => {
+                    get_args(cmd, Some(2), &mut words, &mut args);
+                    commands.push(Command::Arc((args[0].clone(), args[1].clone())));
+                }
+                "CIRCLE" => {
+                    get_args(cmd, Some(1), &mut words, &mut args);
+                    commands.push(Command::Circle(args[0].clone()));
+                }
+                "LABEL" => {
+                    get_args(cmd, Some(1), &mut words, &mut args);
+                    commands.push(Command::Label(args[0].clone()));
+                }
+                "SETLABELFONT" => {
+                    get_args(cmd, Some(1), &mut words, &mut args);
+                    commands.push(Command::SetLabelFont(args[0].clone()));
+                }
+                "SETLABELHEIGHT" => {
+                    get_args(cmd, Some(1), &mut words, &mut args);
+                    commands.push(Command::SetLabelHeight(args[0].clone()));
+                }
                 "SETHEADING" => {
                     get_args(cmd, Some(1), &mut words, &mut args);
                     commands.push(Command::SetHeading(args[0].clone()));
                 }
+                "SETSCALE" => {
+                    get_args(cmd, Some(1), &mut words, &mut args);
+                    commands.push(Command::SetScale(args[0].clone()));
+                }
+                "SETORIGIN" => {
+                    get_args(cmd, Some(2), &mut words, &mut args);
+                    commands.push(Command::SetOrigin((args[0].clone(), args[1].clone())));
+                }
+                "SETYAXIS" => {
+                    get_args(cmd, Some(1), &mut words, &mut args);
+                    commands.push(Command::SetYAxis(args[0].clone()));
+                }
                 "SETX" => {
                     get_args(cmd, Some(1), &mut words, &mut args);
                     commands.push(Command::SetX(args[0].clone()));
@@ -118,6 +217,44 @@ fn parse_lines(
                     i = parse_lines(lines.clone(), i + 1, &mut while_commands, expected_token) - 1;
                     commands.push(Command::While((args[0].clone(), while_commands)));
                 }
+                "LSYSTEM" => {
+                    get_args(cmd, Some(5), &mut words, &mut args);
+                    commands.push(Command::LSystem((
+                        args[0].clone(),
+                        args[1].clone(),
+                        args[2].clone(),
+                        args[3].clone(),
+                        args[4].clone(),
+                    )));
+                }
+                "LSYSTEMACTION" => {
+                    get_args(cmd, Some(2), &mut words, &mut args);
+                    commands.push(Command::LSystemAction((args[0].clone(), args[1].clone())));
+                }
+                "PUSHTURTLE" => {
+                    get_args(cmd, Some(0), &mut words, &mut args);
+                    commands.push(Command::PushTurtle);
+                }
+                "POPTURTLE" => {
+                    get_args(cmd, Some(0), &mut words, &mut args);
+                    commands.push(Command::PopTurtle);
+                }
+                "NEWTURTLE" => {
+                    get_args(cmd, Some(1), &mut words, &mut args);
+                    commands.push(Command::NewTurtle(args[0].clone()));
+                }
+                "TELL" => {
+                    get_args(cmd, Some(1), &mut words, &mut args);
+                    commands.push(Command::Tell(args[0].clone()));
+                }
+                "ASK" => {
+                    get_args(cmd, None, &mut words, &mut args);
+                    check_has_left_bracket(cmd, &mut args);
+                    let mut ask_commands = Vec::new();
+                    expected_token.braces += 1;
+                    i = parse_lines(lines.clone(), i + 1, &mut ask_commands, expected_token) - 1;
+                    commands.push(Command::Ask((args[0].clone(), ask_commands)));
+                }
                 "TO" => {
                     if let Some(var_name) = words.next() {
                         if !is_valid_value(var_name) {
@@ -149,6 +286,10 @@ fn parse_lines(
                 }
             }
         }
+        statements.extend(commands.into_iter().map(|command| Statement {
+            line: line_number,
+            command,
+        }));
         i += 1;
     }
     if expected_token.braces != 0 {
@@ -165,7 +306,7 @@ fn get_args(
     words: &mut SplitWhitespace<'_>,
     args: &mut Vec<Expression>,
 ) {
-    let remaining_args: Vec<&str> = words.collect();
+    let remaining_args: Vec<&str> = words.flat_map(split_brackets).collect();
     match arg_size {
         Some(arg_count) => {
             let tokens = &mut VecDeque::from(remaining_args);
@@ -191,6 +332,27 @@ fn get_args(
     }
 }
 
+/// Separates list brackets from the words they are attached to, so that
+/// `[255 0 0]` is tokenized the same as `[ 255 0 0 ]`. Brackets that are
+/// balanced within a word, such as the L-system replacements `"F[+F]` and
+/// `F[+F]` in `[F F[+F]]`, are part of the word.
+fn split_brackets(word: &str) -> Vec<&str> {
+    let inner = word.trim_start_matches('[');
+    let opening = word.len() - inner.len();
+    let unmatched = inner
+        .matches(']')
+        .count()
+        .saturating_sub(inner.matches('[').count());
+    let closing = (inner.len() - inner.trim_end_matches(']').len()).min(unmatched);
+    let trimmed = &inner[..inner.len() - closing];
+    let mut tokens = vec!["["; opening];
+    if !trimmed.is_empty() {
+        tokens.push(trimmed);
+    }
+    tokens.extend(vec!["]"; closing]);
+    tokens
+}
+
 fn check_has_left_bracket(cmd: &str, args: &mut Vec<Expression>) {
     if args.len() != 2 {
         exit_with_error(format!("Error: incorrect expression in '{cmd}' command"))
@@ -0,0 +1,214 @@
+use std::str::FromStr;
+
+use resvg::tiny_skia::Pixmap;
+use unsvg::Color;
+
+/// How much a pixel's channels must differ from the background for it to
+/// count as drawn, so faint antialiasing doesn't blur the preview.
+const THRESHOLD: u8 = 48;
+
+/// The characters a preview is drawn with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PreviewStyle {
+    /// Braille patterns, with 2 by 4 dots in each character.
+    Braille,
+    /// Half blocks, with two pixels stacked in each character.
+    Blocks,
+}
+
+impl FromStr for PreviewStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "braille" => Ok(PreviewStyle::Braille),
+            "blocks" => Ok(PreviewStyle::Blocks),
+            _ => Err(format!(
+                "Unknown preview style '{s}'. Expected braille or blocks"
+            )),
+        }
+    }
+}
+
+/// How a drawing is previewed in the terminal.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PreviewOptions {
+    pub style: PreviewStyle,
+    /// The most characters printed on each line.
+    pub columns: usize,
+}
+
+impl Default for PreviewOptions {
+    fn default() -> Self {
+        PreviewOptions {
+            style: PreviewStyle::Braille,
+            columns: 80,
+        }
+    }
+}
+
+/// A drawn dot of the preview and its color.
+type Dot = Option<Color>;
+
+/// Draws `pixmap` as text, coloring it with ANSI escape codes. The image is
+/// scaled down to fit `options.columns`, and a dot is drawn wherever any
+/// pixel it covers differs from `background`.
+pub fn preview(pixmap: &Pixmap, background: Color, options: &PreviewOptions) -> String {
+    let (cell_width, cell_height) = match options.style {
+        PreviewStyle::Braille => (2, 4),
+        PreviewStyle::Blocks => (1, 2),
+    };
+    let dots = sample(pixmap, background, options.columns.max(1) * cell_width);
+    let rows = dots.len().div_ceil(cell_height);
+    let columns = dots.first().map_or(0, |row| row.len().div_ceil(cell_width));
+    let dot =
+        |x: usize, y: usize| -> Dot { dots.get(y).and_then(|row| row.get(x)).copied().flatten() };
+
+    let mut text = String::new();
+    for row in 0..rows {
+        let mut line = Line::default();
+        for column in 0..columns {
+            let (x, y) = (column * cell_width, row * cell_height);
+            match options.style {
+                PreviewStyle::Braille => {
+                    // Braille dots are numbered down the left column, then
+                    // down the right, with the bottom row numbered last.
+                    let cell = [
+                        dot(x, y),
+                        dot(x, y + 1),
+                        dot(x, y + 2),
+                        dot(x + 1, y),
+                        dot(x + 1, y + 1),
+                        dot(x + 1, y + 2),
+                        dot(x, y + 3),
+                        dot(x + 1, y + 3),
+                    ];
+                    let pattern = cell
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, dot)| dot.is_some())
+                        .fold(0, |pattern, (i, _)| pattern | 1 << i);
+                    // The empty pattern is drawn as a space, which some
+                    // fonts draw narrower.
+                    let character = match pattern {
+                        0 => ' ',
+                        _ => char::from_u32(0x2800 + pattern).unwrap(),
+                    };
+                    line.push(character, most_common(&cell), None);
+                }
+                PreviewStyle::Blocks => match (dot(x, y), dot(x, y + 1)) {
+                    (None, None) => line.push(' ', None, None),
+                    (Some(top), None) => line.push('▀', Some(top), None),
+                    (None, Some(bottom)) => line.push('▄', Some(bottom), None),
+                    (Some(top), Some(bottom)) if top == bottom => line.push('█', Some(top), None),
+                    (Some(top), Some(bottom)) => line.push('▀', Some(top), Some(bottom)),
+                },
+            }
+        }
+        text.push_str(&line.finish());
+        text.push('\n');
+    }
+    text
+}
+
+/// Scales `pixmap` down to at most `width` dots across, keeping its aspect
+/// ratio. Each dot takes the color of the pixel it covers that differs most
+/// from the background.
+fn sample(pixmap: &Pixmap, background: Color, width: usize) -> Vec<Vec<Dot>> {
+    let (pixmap_width, pixmap_height) = (pixmap.width() as usize, pixmap.height() as usize);
+    let scale = (pixmap_width as f32 / width as f32).max(1.0);
+    let columns = (pixmap_width as f32 / scale).ceil() as usize;
+    let rows = (pixmap_height as f32 / scale).ceil() as usize;
+    let span = |i: usize, length: usize| {
+        let start = (i as f32 * scale) as usize;
+        let end = (((i + 1) as f32 * scale) as usize).clamp(start + 1, length);
+        start..end
+    };
+
+    (0..rows)
+        .map(|row| {
+            (0..columns)
+                .map(|column| {
+                    let mut dot: Option<(u8, Color)> = None;
+                    for y in span(row, pixmap_height) {
+                        for x in span(column, pixmap_width) {
+                            let pixel = pixmap.pixel(x as u32, y as u32).unwrap().demultiply();
+                            let color = Color {
+                                red: pixel.red(),
+                                green: pixel.green(),
+                                blue: pixel.blue(),
+                            };
+                            let difference = color
+                                .red
+                                .abs_diff(background.red)
+                                .max(color.green.abs_diff(background.green))
+                                .max(color.blue.abs_diff(background.blue));
+                            let stronger = match dot {
+                                Some((most, _)) => difference > most,
+                                None => true,
+                            };
+                            if difference >= THRESHOLD && stronger {
+                                dot = Some((difference, color));
+                            }
+                        }
+                    }
+                    dot.map(|(_, color)| color)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// The color drawn most often among `dots`, preferring the first on a tie.
+fn most_common(dots: &[Dot]) -> Option<Color> {
+    let colors: Vec<Color> = dots.iter().flatten().copied().collect();
+    colors.iter().copied().max_by_key(|&color| {
+        let count = colors.iter().filter(|&&c| c == color).count();
+        let first = colors.iter().position(|&c| c == color).unwrap();
+        (count, usize::MAX - first)
+    })
+}
+
+/// A line of preview text, only writing escape codes where the colors change.
+#[derive(Default)]
+struct Line {
+    text: String,
+    colors: (Option<Color>, Option<Color>),
+}
+
+impl Line {
+    fn push(&mut self, character: char, foreground: Option<Color>, background: Option<Color>) {
+        // Blank characters look the same in any foreground color.
+        let foreground = if character == ' ' {
+            self.colors.0
+        } else {
+            foreground
+        };
+        if (foreground, background) != self.colors {
+            if self.colors != (None, None) {
+                self.text.push_str("\x1b[0m");
+            }
+            if let Some(color) = foreground {
+                self.text.push_str(&format!(
+                    "\x1b[38;2;{};{};{}m",
+                    color.red, color.green, color.blue
+                ));
+            }
+            if let Some(color) = background {
+                self.text.push_str(&format!(
+                    "\x1b[48;2;{};{};{}m",
+                    color.red, color.green, color.blue
+                ));
+            }
+            self.colors = (foreground, background);
+        }
+        self.text.push(character);
+    }
+
+    fn finish(mut self) -> String {
+        if self.colors != (None, None) {
+            self.text.push_str("\x1b[0m");
+        }
+        self.text
+    }
+}
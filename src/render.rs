@@ -0,0 +1,177 @@
+use std::path::Path;
+
+use unsvg::Color;
+
+use crate::canvas::{Font, StrokeStyle};
+use crate::display::{draw, Primitive};
+
+/// An output backend driven by the executor. Every position and length is
+/// given in canvas pixels, after the user coordinate system is applied.
+pub trait Renderer {
+    /// The size of the drawing area in pixels.
+    fn dimensions(&self) -> (u32, u32);
+
+    /// Note the source line of the command that draws next. Backends that
+    /// don't track where primitives came from can ignore it.
+    fn set_line(&mut self, _line: usize) {}
+
+    /// Set the color and style used by later `line_to` and `arc` calls.
+    fn set_stroke(&mut self, color: Color, style: &StrokeStyle);
+
+    /// Move to `point` without drawing.
+    fn move_to(&mut self, point: (f32, f32));
+
+    /// Draw a straight line from the current point to `point`, which then
+    /// becomes the current point.
+    fn line_to(&mut self, point: (f32, f32));
+
+    /// Draw an arc of the circle around `center`, starting at `heading` and
+    /// sweeping clockwise through `angle` degrees (counterclockwise if
+    /// negative). A sweep of 360 degrees or more draws the whole circle.
+    fn arc(&mut self, center: (f32, f32), heading: f32, angle: f32, radius: f32);
+
+    /// Write `text` with its baseline starting at `position`, reading along
+    /// `heading`.
+    fn text(&mut self, position: (f32, f32), heading: i32, text: &str, font: &Font, color: Color);
+
+    /// A marker for everything drawn so far, so that a later `fill` can be
+    /// placed beneath what is drawn after it.
+    fn layer(&self) -> usize;
+
+    /// Fill the polygon through `points` beneath everything drawn since
+    /// `layer`.
+    fn fill(&mut self, layer: usize, points: &[(f32, f32)], color: Color);
+
+    /// Flood fill the region around `point` with `color`.
+    fn flood_fill(&mut self, point: (f32, f32), color: Color);
+
+    /// Draw `shape`, whose coordinates are relative to a turtle at the origin
+    /// heading up, at `position` and turned clockwise through `heading`
+    /// degrees. Backends that can reuse a shape may draw it once and refer
+    /// back to it.
+    fn stamp(&mut self, shape: &[Primitive], position: (f32, f32), heading: f32) {
+        for primitive in shape {
+            draw(self, &primitive.placed(position, heading));
+        }
+    }
+
+    /// Write the finished drawing to `path`.
+    fn finish(&mut self, path: &Path) -> Result<(), String>;
+}
+
+/// Largest angle, in degrees, drawn as a single straight segment when an arc
+/// is approximated by lines.
+const ARC_STEP: f32 = 5.0;
+
+/// Points along an arc, as passed to `Renderer::arc`, for backends that can
+/// only draw straight lines.
+pub fn arc_points((x, y): (f32, f32), heading: f32, angle: f32, radius: f32) -> Vec<(f32, f32)> {
+    let angle = angle.clamp(-360.0, 360.0);
+    let point_at = |degrees: f32| {
+        // Headings start at 0 degrees pointing up and increase clockwise.
+        let radians = (degrees - 90.0).to_radians();
+        (x + radians.cos() * radius, y + radians.sin() * radius)
+    };
+    let steps = (angle.abs() / ARC_STEP).ceil().max(1.0) as usize;
+    (0..=steps)
+        .map(|step| point_at(heading + angle * step as f32 / steps as f32))
+        .collect()
+}
+
+/// Warn that pen alpha and gradients were drawn as plain strokes, for vector
+/// backends that can't show them.
+pub fn warn_stroke_effects_unsupported() {
+    eprintln!("Warning: Pen alpha and gradients are only supported for svg and png output");
+}
+
+/// A backend that draws with a pen, so can only draw straight lines. Wrap it
+/// in a [`PenPlotter`] to drive it as a [`Renderer`].
+pub trait Plotter {
+    /// The output format, as named in warnings.
+    const FORMAT: &'static str;
+
+    /// The size of the drawing area in pixels.
+    fn dimensions(&self) -> (u32, u32);
+
+    /// Set the color and style used by later lines.
+    fn set_stroke(&mut self, color: Color, style: &StrokeStyle);
+
+    /// Draw a straight line between two points.
+    fn line(&mut self, from: (f32, f32), to: (f32, f32));
+
+    /// Write the finished drawing to `path`.
+    fn finish(&mut self, path: &Path) -> Result<(), String>;
+}
+
+/// Drives a [`Plotter`]. Arcs are drawn as runs of straight lines, and
+/// labels and fills, which can't be drawn with a pen, are left out with a
+/// warning.
+pub struct PenPlotter<P> {
+    plotter: P,
+    /// Where the next line starts, in canvas pixels.
+    position: (f32, f32),
+    skipped: bool,
+}
+
+impl<P: Plotter> PenPlotter<P> {
+    pub fn new(plotter: P) -> PenPlotter<P> {
+        PenPlotter {
+            plotter,
+            position: (0.0, 0.0),
+            skipped: false,
+        }
+    }
+}
+
+impl<P: Plotter> Renderer for PenPlotter<P> {
+    fn dimensions(&self) -> (u32, u32) {
+        self.plotter.dimensions()
+    }
+
+    fn set_stroke(&mut self, color: Color, style: &StrokeStyle) {
+        self.plotter.set_stroke(color, style);
+    }
+
+    fn move_to(&mut self, point: (f32, f32)) {
+        self.position = point;
+    }
+
+    fn line_to(&mut self, point: (f32, f32)) {
+        self.plotter.line(self.position, point);
+        self.position = point;
+    }
+
+    fn arc(&mut self, center: (f32, f32), heading: f32, angle: f32, radius: f32) {
+        let points = arc_points(center, heading, angle, radius);
+        self.move_to(points[0]);
+        for &point in &points[1..] {
+            self.line_to(point);
+        }
+    }
+
+    fn text(&mut self, _: (f32, f32), _: i32, _: &str, _: &Font, _: Color) {
+        self.skipped = true;
+    }
+
+    fn layer(&self) -> usize {
+        0
+    }
+
+    fn fill(&mut self, _: usize, _: &[(f32, f32)], _: Color) {
+        self.skipped = true;
+    }
+
+    fn flood_fill(&mut self, _: (f32, f32), _: Color) {
+        self.skipped = true;
+    }
+
+    fn finish(&mut self, path: &Path) -> Result<(), String> {
+        if self.skipped {
+            eprintln!(
+                "Warning: Labels and fills are not included in {} output",
+                P::FORMAT
+            );
+        }
+        self.plotter.finish(path)
+    }
+}
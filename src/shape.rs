@@ -0,0 +1,36 @@
+use unsvg::Color;
+
+use crate::display::Primitive;
+
+/// The default turtle, a triangle pointing along the heading, in pixels
+/// relative to a turtle at the origin heading up.
+pub const TRIANGLE: [(f32, f32); 3] = [(0.0, -8.0), (5.0, 5.0), (-5.0, 5.0)];
+
+/// What `STAMP` and a shown turtle draw. Coordinates are canvas pixels
+/// relative to a turtle at the origin heading up.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Shape {
+    /// A polygon filled with the turtle's pen color.
+    Polygon(Vec<(f32, f32)>),
+    /// A drawing recorded from a procedure, in the colors it was drawn in.
+    Drawing(Vec<Primitive>),
+}
+
+impl Default for Shape {
+    fn default() -> Self {
+        Shape::Polygon(TRIANGLE.to_vec())
+    }
+}
+
+impl Shape {
+    /// The primitives drawn for this shape by a turtle with pen `color`.
+    pub fn primitives(&self, color: Color) -> Vec<Primitive> {
+        match self {
+            Shape::Polygon(points) => vec![Primitive::Fill {
+                points: points.clone(),
+                color,
+            }],
+            Shape::Drawing(primitives) => primitives.clone(),
+        }
+    }
+}
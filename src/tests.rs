@@ -1,143 +1,695 @@
-#[cfg(test)]
-pub mod tests {
-    use std::{
-        fs::{self},
-        path::PathBuf,
-    };
+use std::{
+    fs::{self},
+    path::{Path, PathBuf},
+};
 
-    use crate::utils::start;
+use clap::Parser;
+use unsvg::{Color, COLORS};
 
-    const WIDTH: u32 = 200;
-    const HEIGHT: u32 = 200;
+use crate::{
+    canvas::{
+        format_number, Animation, Canvas, Font, Framing, StrokeStyle, SvgOptions, MAX_PRECISION,
+    },
+    display::{DisplayList, Primitive},
+    optimize::optimize_paths,
+    preview::{preview, PreviewOptions, PreviewStyle},
+    render::Renderer,
+    utils::{run, start, start_with_options, OutputOptions},
+    Args,
+};
 
-    #[test]
-    fn test_logo_examples_success() {
-        let logo_examples = fs::read_dir("logo_examples").unwrap();
-        let logo_examples_out = fs::read_dir("logo_examples_out").unwrap();
-        let mut file_inputs: Vec<_> = Vec::new();
-        let mut file_outputs: Vec<_> = Vec::new();
-        for path in logo_examples {
-            file_inputs.push(path.unwrap().path());
-        }
-        for path in logo_examples_out {
-            file_outputs.push(path.unwrap().path());
-        }
-        file_inputs.sort();
-        file_outputs.sort();
-        for (i, file) in file_inputs.iter().enumerate() {
-            let image_path: PathBuf = "result.svg".into();
-            if let Ok(exp) = fs::read_to_string(file_outputs[i].clone()) {
-                if exp.trim() != "Error" {
-                    let _ = start(file.to_path_buf(), image_path.clone(), WIDTH, HEIGHT);
-                    if let Ok(out) = fs::read_to_string(image_path) {
-                        assert_eq!(out, exp);
-                    }
+const WIDTH: u32 = 200;
+const HEIGHT: u32 = 200;
+
+#[test]
+fn test_logo_examples_success() {
+    let logo_examples = fs::read_dir("logo_examples").unwrap();
+    let logo_examples_out = fs::read_dir("logo_examples_out").unwrap();
+    let mut file_inputs: Vec<_> = Vec::new();
+    let mut file_outputs: Vec<_> = Vec::new();
+    for path in logo_examples {
+        file_inputs.push(path.unwrap().path());
+    }
+    for path in logo_examples_out {
+        file_outputs.push(path.unwrap().path());
+    }
+    file_inputs.sort();
+    file_outputs.sort();
+    for (i, file) in file_inputs.iter().enumerate() {
+        let image_path: PathBuf = "result.svg".into();
+        if let Ok(exp) = fs::read_to_string(file_outputs[i].clone()) {
+            if exp.trim() != "Error" {
+                let _ = start(file.to_path_buf(), image_path.clone(), WIDTH, HEIGHT);
+                if let Ok(out) = fs::read_to_string(image_path) {
+                    assert_eq!(out, exp);
                 }
             }
         }
     }
+}
 
-    #[test]
-    #[should_panic]
-    fn example_too_many_args_err() {
-        let file_name: PathBuf = "logo_examples/1_09_too_many_args_err.lg".into();
-        let image_path: PathBuf = "result.svg".into();
-        let _ = start(file_name.to_path_buf(), image_path.clone(), WIDTH, HEIGHT);
-    }
+#[test]
+#[should_panic]
+fn example_too_many_args_err() {
+    let file_name: PathBuf = "logo_examples/1_09_too_many_args_err.lg".into();
+    let image_path: PathBuf = "result.svg".into();
+    let _ = start(file_name.to_path_buf(), image_path.clone(), WIDTH, HEIGHT);
+}
 
-    #[test]
-    #[should_panic]
-    fn example_too_many_args_2_err() {
-        let file_name: PathBuf = "logo_examples/1_10_too_many_args_2_err.lg".into();
-        let image_path: PathBuf = "result.svg".into();
-        let _ = start(file_name.to_path_buf(), image_path.clone(), WIDTH, HEIGHT);
-    }
+#[test]
+#[should_panic]
+fn example_too_many_args_2_err() {
+    let file_name: PathBuf = "logo_examples/1_10_too_many_args_2_err.lg".into();
+    let image_path: PathBuf = "result.svg".into();
+    let _ = start(file_name.to_path_buf(), image_path.clone(), WIDTH, HEIGHT);
+}
 
-    #[test]
-    #[should_panic]
-    fn example_wrong_type_err() {
-        let file_name: PathBuf = "logo_examples/1_11_wrong_type_err.lg".into();
-        let image_path: PathBuf = "result.svg".into();
-        let _ = start(file_name.to_path_buf(), image_path.clone(), WIDTH, HEIGHT);
+#[test]
+#[should_panic]
+fn example_wrong_type_err() {
+    let file_name: PathBuf = "logo_examples/1_11_wrong_type_err.lg".into();
+    let image_path: PathBuf = "result.svg".into();
+    let _ = start(file_name.to_path_buf(), image_path.clone(), WIDTH, HEIGHT);
+}
+
+#[test]
+#[should_panic]
+fn example_1_12_not_enough_args_err() {
+    let file_name: PathBuf = "logo_examples/1_12_not_enough_args_err.lg".into();
+    let image_path: PathBuf = "result.svg".into();
+    let _ = start(file_name.to_path_buf(), image_path.clone(), WIDTH, HEIGHT);
+}
+
+#[test]
+#[should_panic]
+fn example_1_13_wrong_type_err() {
+    let file_name: PathBuf = "logo_examples/1_13_wrong_type_err.lg".into();
+    let image_path: PathBuf = "result.svg".into();
+    let _ = start(file_name.to_path_buf(), image_path.clone(), WIDTH, HEIGHT);
+}
+
+#[test]
+#[should_panic]
+fn example_2_03_missing_var_err() {
+    let file_name: PathBuf = "logo_examples/2_03_missing_var_err.lg".into();
+    let image_path: PathBuf = "result.svg".into();
+    let _ = start(file_name.to_path_buf(), image_path.clone(), WIDTH, HEIGHT);
+}
+
+#[test]
+#[should_panic]
+fn example_2_06_unknownaddassign_err() {
+    let file_name: PathBuf = "logo_examples/2_06_unknownaddassign_err.lg".into();
+    let image_path: PathBuf = "result.svg".into();
+    let _ = start(file_name.to_path_buf(), image_path.clone(), WIDTH, HEIGHT);
+}
+
+#[test]
+#[should_panic]
+fn example_3_07_invalid_if_err() {
+    let file_name: PathBuf = "logo_examples/3_07_invalid_if_err.lg".into();
+    let image_path: PathBuf = "result.svg".into();
+    let _ = start(file_name.to_path_buf(), image_path.clone(), WIDTH, HEIGHT);
+}
+
+#[test]
+#[should_panic]
+fn example_3_08_invalid_while_err() {
+    let file_name: PathBuf = "logo_examples/3_08_invalid_while_err.lg".into();
+    let image_path: PathBuf = "result.svg".into();
+    let _ = start(file_name.to_path_buf(), image_path.clone(), WIDTH, HEIGHT);
+}
+
+#[test]
+#[should_panic]
+fn example_5_03_invalid_err() {
+    let file_name: PathBuf = "logo_examples/5_03_invalid_err.lg".into();
+    let image_path: PathBuf = "result.svg".into();
+    let _ = start(file_name.to_path_buf(), image_path.clone(), WIDTH, HEIGHT);
+}
+
+#[test]
+#[should_panic]
+fn example_5_04_invalid_end_err() {
+    let file_name: PathBuf = "logo_examples/5_04_invalid_end_err.lg".into();
+    let image_path: PathBuf = "result.svg".into();
+    let _ = start(file_name.to_path_buf(), image_path.clone(), WIDTH, HEIGHT);
+}
+
+#[test]
+#[should_panic]
+fn invalid_var_name() {
+    let file_name: PathBuf = "tests/invalid_var_name.lg".into();
+    let image_path: PathBuf = "result.svg".into();
+    let _ = start(file_name.to_path_buf(), image_path.clone(), WIDTH, HEIGHT);
+}
+
+#[test]
+#[should_panic]
+fn divide_by_0() {
+    let file_name: PathBuf = "tests/divide_by_0.lg".into();
+    let image_path: PathBuf = "result.svg".into();
+    let _ = start(file_name.to_path_buf(), image_path.clone(), WIDTH, HEIGHT);
+}
+
+#[test]
+#[should_panic]
+fn towards_missing_arg() {
+    let file_name: PathBuf = "tests/towards_missing_arg.lg".into();
+    let image_path: PathBuf = "result.svg".into();
+    let _ = start(file_name.to_path_buf(), image_path.clone(), WIDTH, HEIGHT);
+}
+
+#[test]
+#[should_panic]
+fn invalid_rgb_color() {
+    let file_name: PathBuf = "tests/invalid_rgb_color.lg".into();
+    let image_path: PathBuf = "result.svg".into();
+    let _ = start(file_name.to_path_buf(), image_path.clone(), WIDTH, HEIGHT);
+}
+
+#[test]
+#[should_panic]
+fn palette_index_too_big() {
+    let file_name: PathBuf = "tests/palette_index_too_big.lg".into();
+    let image_path: PathBuf = "result.svg".into();
+    let _ = start(file_name.to_path_buf(), image_path.clone(), WIDTH, HEIGHT);
+}
+
+#[test]
+#[should_panic]
+fn infinite_pen_size() {
+    let file_name: PathBuf = "tests/infinite_pen_size.lg".into();
+    let image_path: PathBuf = "result.svg".into();
+    let _ = start(file_name.to_path_buf(), image_path.clone(), WIDTH, HEIGHT);
+}
+
+#[test]
+#[should_panic]
+fn invalid_line_cap() {
+    let file_name: PathBuf = "tests/invalid_line_cap.lg".into();
+    let image_path: PathBuf = "result.svg".into();
+    let _ = start(file_name.to_path_buf(), image_path.clone(), WIDTH, HEIGHT);
+}
+
+#[test]
+#[should_panic]
+fn endfill_without_beginfill() {
+    let file_name: PathBuf = "tests/endfill_without_beginfill.lg".into();
+    let image_path: PathBuf = "result.svg".into();
+    let _ = start(file_name.to_path_buf(), image_path.clone(), WIDTH, HEIGHT);
+}
+
+#[test]
+#[should_panic]
+fn fill_in_svg() {
+    let file_name: PathBuf = "tests/fill_in_svg.lg".into();
+    let image_path: PathBuf = "result.svg".into();
+    let _ = start(file_name.to_path_buf(), image_path.clone(), WIDTH, HEIGHT);
+}
+
+#[test]
+#[should_panic]
+fn fill_in_shape() {
+    let file_name: PathBuf = "tests/fill_in_shape.lg".into();
+    let image_path: PathBuf = "result.png".into();
+    let _ = start(file_name.to_path_buf(), image_path.clone(), WIDTH, HEIGHT);
+}
+
+#[test]
+#[should_panic]
+fn shape_changes_scale() {
+    let file_name: PathBuf = "tests/shape_changes_scale.lg".into();
+    let image_path: PathBuf = "result.svg".into();
+    let _ = start(file_name.to_path_buf(), image_path.clone(), WIDTH, HEIGHT);
+}
+
+#[test]
+#[should_panic]
+fn negative_radius() {
+    let file_name: PathBuf = "tests/negative_radius.lg".into();
+    let image_path: PathBuf = "result.svg".into();
+    let _ = start(file_name.to_path_buf(), image_path.clone(), WIDTH, HEIGHT);
+}
+
+#[test]
+#[should_panic]
+fn invalid_label_height() {
+    let file_name: PathBuf = "tests/invalid_label_height.lg".into();
+    let image_path: PathBuf = "result.svg".into();
+    let _ = start(file_name.to_path_buf(), image_path.clone(), WIDTH, HEIGHT);
+}
+
+#[test]
+#[should_panic]
+fn invalid_pen_alpha() {
+    let file_name: PathBuf = "tests/invalid_pen_alpha.lg".into();
+    let image_path: PathBuf = "result.svg".into();
+    let _ = start(file_name.to_path_buf(), image_path.clone(), WIDTH, HEIGHT);
+}
+
+#[test]
+#[should_panic]
+fn tell_unknown_turtle() {
+    let file_name: PathBuf = "tests/tell_unknown_turtle.lg".into();
+    let image_path: PathBuf = "result.svg".into();
+    let _ = start(file_name.to_path_buf(), image_path.clone(), WIDTH, HEIGHT);
+}
+
+#[test]
+#[should_panic]
+fn pop_empty_turtle_stack() {
+    let file_name: PathBuf = "tests/pop_empty_turtle_stack.lg".into();
+    let image_path: PathBuf = "result.svg".into();
+    let _ = start(file_name.to_path_buf(), image_path.clone(), WIDTH, HEIGHT);
+}
+
+#[test]
+#[should_panic]
+fn lsystem_too_large() {
+    let file_name: PathBuf = "tests/lsystem_too_large.lg".into();
+    let image_path: PathBuf = "result.svg".into();
+    let _ = start(file_name.to_path_buf(), image_path.clone(), WIDTH, HEIGHT);
+}
+
+#[test]
+#[should_panic]
+fn invalid_scale() {
+    let file_name: PathBuf = "tests/invalid_scale.lg".into();
+    let image_path: PathBuf = "result.svg".into();
+    let _ = start(file_name.to_path_buf(), image_path.clone(), WIDTH, HEIGHT);
+}
+
+#[test]
+#[should_panic]
+fn infinite_length() {
+    let file_name: PathBuf = "tests/infinite_length.lg".into();
+    let image_path: PathBuf = "result.svg".into();
+    let _ = start(file_name.to_path_buf(), image_path.clone(), WIDTH, HEIGHT);
+}
+
+#[test]
+fn crop_output() {
+    let file_name: PathBuf = "tests/crop_output.lg".into();
+    let image_path: PathBuf = "crop_result.svg".into();
+    let options = OutputOptions {
+        framing: Framing::Crop { padding: 10.0 },
+        ..OutputOptions::default()
+    };
+    let _ = start_with_options(file_name, image_path.clone(), WIDTH, HEIGHT, options);
+    let out = fs::read_to_string(image_path).unwrap();
+    let _ = fs::remove_file("crop_result.svg");
+    assert_eq!(out, fs::read_to_string("tests/crop_output.svg").unwrap());
+}
+
+#[test]
+fn fit_output() {
+    let file_name: PathBuf = "tests/fit_output.lg".into();
+    let image_path: PathBuf = "fit_result.svg".into();
+    let options = OutputOptions {
+        framing: Framing::Fit { margin: 10.0 },
+        ..OutputOptions::default()
+    };
+    let _ = start_with_options(file_name, image_path.clone(), WIDTH, HEIGHT, options);
+    let out = fs::read_to_string(image_path).unwrap();
+    let _ = fs::remove_file("fit_result.svg");
+    assert_eq!(out, fs::read_to_string("tests/fit_output.svg").unwrap());
+}
+
+#[test]
+fn show_turtle() {
+    let file_name: PathBuf = "tests/show_turtle.lg".into();
+    let image_path: PathBuf = "turtle_result.svg".into();
+    let _ = start(file_name, image_path.clone(), WIDTH, HEIGHT);
+    let out = fs::read_to_string(image_path).unwrap();
+    let _ = fs::remove_file("turtle_result.svg");
+    assert_eq!(out, fs::read_to_string("tests/show_turtle.svg").unwrap());
+}
+
+#[test]
+fn stamp_symbols() {
+    let file_name: PathBuf = "tests/stamp.lg".into();
+    let image_path: PathBuf = "stamp_result.svg".into();
+    let _ = start(file_name, image_path.clone(), WIDTH, HEIGHT);
+    let out = fs::read_to_string(image_path).unwrap();
+    let _ = fs::remove_file("stamp_result.svg");
+    assert_eq!(out, fs::read_to_string("tests/stamp.svg").unwrap());
+}
+
+#[test]
+fn queries_in_expressions() {
+    let file_name: PathBuf = "tests/queries_in_expressions.lg".into();
+    let image_path: PathBuf = "queries_in_expressions_result.svg".into();
+    let _ = start(file_name, image_path.clone(), WIDTH, HEIGHT);
+    let out = fs::read_to_string(image_path).unwrap();
+    let _ = fs::remove_file("queries_in_expressions_result.svg");
+    assert_eq!(
+        out,
+        fs::read_to_string("tests/queries_in_expressions.svg").unwrap()
+    );
+}
+
+#[test]
+fn fractional_queries() {
+    let file_name: PathBuf = "tests/fractional_queries.lg".into();
+    let image_path: PathBuf = "fractional_queries_result.svg".into();
+    let _ = start(file_name, image_path.clone(), WIDTH, HEIGHT);
+    let out = fs::read_to_string(image_path).unwrap();
+    let _ = fs::remove_file("fractional_queries_result.svg");
+    assert_eq!(
+        out,
+        fs::read_to_string("tests/fractional_queries.svg").unwrap()
+    );
+}
+
+#[test]
+fn lsystem_brackets() {
+    let file_name: PathBuf = "tests/lsystem_brackets.lg".into();
+    let image_path: PathBuf = "lsystem_brackets_result.svg".into();
+    let _ = start(file_name, image_path.clone(), WIDTH, HEIGHT);
+    let out = fs::read_to_string(image_path).unwrap();
+    let _ = fs::remove_file("lsystem_brackets_result.svg");
+    assert_eq!(
+        out,
+        fs::read_to_string("tests/lsystem_brackets.svg").unwrap()
+    );
+}
+
+#[test]
+fn variable_scope() {
+    let file_name: PathBuf = "tests/variable_scope.lg".into();
+    let image_path: PathBuf = "variable_scope_result.svg".into();
+    let _ = start(file_name, image_path.clone(), WIDTH, HEIGHT);
+    let out = fs::read_to_string(image_path).unwrap();
+    let _ = fs::remove_file("variable_scope_result.svg");
+    assert_eq!(out, fs::read_to_string("tests/variable_scope.svg").unwrap());
+}
+
+#[test]
+fn background_color() {
+    let file_name: PathBuf = "tests/background.lg".into();
+    let image_path: PathBuf = "background_result.svg".into();
+    let _ = start(file_name, image_path.clone(), WIDTH, HEIGHT);
+    let out = fs::read_to_string(image_path).unwrap();
+    let _ = fs::remove_file("background_result.svg");
+    assert_eq!(out, fs::read_to_string("tests/background.svg").unwrap());
+}
+
+#[test]
+fn pen_alpha_and_gradients() {
+    let file_name: PathBuf = "tests/pen_effects.lg".into();
+    let image_path: PathBuf = "pen_effects_result.svg".into();
+    let _ = start(file_name, image_path.clone(), WIDTH, HEIGHT);
+    let out = fs::read_to_string(image_path).unwrap();
+    let _ = fs::remove_file("pen_effects_result.svg");
+    assert_eq!(out, fs::read_to_string("tests/pen_effects.svg").unwrap());
+}
+
+#[test]
+fn circle_gradient() {
+    let file_name: PathBuf = "tests/circle_gradient.lg".into();
+    let image_path: PathBuf = "circle_gradient_result.svg".into();
+    let _ = start(file_name, image_path.clone(), WIDTH, HEIGHT);
+    let out = fs::read_to_string(image_path).unwrap();
+    let _ = fs::remove_file("circle_gradient_result.svg");
+    assert_eq!(
+        out,
+        fs::read_to_string("tests/circle_gradient.svg").unwrap()
+    );
+}
+
+/// Records the calls the executor makes, so tests can check what would be
+/// drawn without rendering anything.
+#[derive(Default)]
+struct RecordingRenderer {
+    calls: Vec<String>,
+}
+
+impl Renderer for RecordingRenderer {
+    fn dimensions(&self) -> (u32, u32) {
+        (WIDTH, HEIGHT)
     }
 
-    #[test]
-    #[should_panic]
-    fn example_1_12_not_enough_args_err() {
-        let file_name: PathBuf = "logo_examples/1_12_not_enough_args_err.lg".into();
-        let image_path: PathBuf = "result.svg".into();
-        let _ = start(file_name.to_path_buf(), image_path.clone(), WIDTH, HEIGHT);
+    fn set_stroke(&mut self, color: Color, style: &StrokeStyle) {
+        let (r, g, b) = (color.red, color.green, color.blue);
+        self.calls
+            .push(format!("set_stroke [{r} {g} {b}] {}", style.width));
     }
 
-    #[test]
-    #[should_panic]
-    fn example_1_13_wrong_type_err() {
-        let file_name: PathBuf = "logo_examples/1_13_wrong_type_err.lg".into();
-        let image_path: PathBuf = "result.svg".into();
-        let _ = start(file_name.to_path_buf(), image_path.clone(), WIDTH, HEIGHT);
+    fn move_to(&mut self, (x, y): (f32, f32)) {
+        self.calls.push(format!("move_to {x} {y}"));
     }
 
-    #[test]
-    #[should_panic]
-    fn example_2_03_missing_var_err() {
-        let file_name: PathBuf = "logo_examples/2_03_missing_var_err.lg".into();
-        let image_path: PathBuf = "result.svg".into();
-        let _ = start(file_name.to_path_buf(), image_path.clone(), WIDTH, HEIGHT);
+    fn line_to(&mut self, (x, y): (f32, f32)) {
+        self.calls.push(format!("line_to {x} {y}"));
     }
 
-    #[test]
-    #[should_panic]
-    fn example_2_06_unknownaddassign_err() {
-        let file_name: PathBuf = "logo_examples/2_06_unknownaddassign_err.lg".into();
-        let image_path: PathBuf = "result.svg".into();
-        let _ = start(file_name.to_path_buf(), image_path.clone(), WIDTH, HEIGHT);
+    fn arc(&mut self, (x, y): (f32, f32), heading: f32, angle: f32, radius: f32) {
+        self.calls
+            .push(format!("arc {x} {y} {heading} {angle} {radius}"));
     }
 
-    #[test]
-    #[should_panic]
-    fn example_3_07_invalid_if_err() {
-        let file_name: PathBuf = "logo_examples/3_07_invalid_if_err.lg".into();
-        let image_path: PathBuf = "result.svg".into();
-        let _ = start(file_name.to_path_buf(), image_path.clone(), WIDTH, HEIGHT);
+    fn text(&mut self, (x, y): (f32, f32), _: i32, text: &str, _: &Font, _: Color) {
+        self.calls.push(format!("text {x} {y} {text}"));
     }
 
-    #[test]
-    #[should_panic]
-    fn example_3_08_invalid_while_err() {
-        let file_name: PathBuf = "logo_examples/3_08_invalid_while_err.lg".into();
-        let image_path: PathBuf = "result.svg".into();
-        let _ = start(file_name.to_path_buf(), image_path.clone(), WIDTH, HEIGHT);
+    fn layer(&self) -> usize {
+        self.calls.len()
     }
 
-    #[test]
-    #[should_panic]
-    fn example_5_03_invalid_err() {
-        let file_name: PathBuf = "logo_examples/5_03_invalid_err.lg".into();
-        let image_path: PathBuf = "result.svg".into();
-        let _ = start(file_name.to_path_buf(), image_path.clone(), WIDTH, HEIGHT);
+    fn fill(&mut self, layer: usize, points: &[(f32, f32)], _: Color) {
+        self.calls.push(format!("fill {layer} {}", points.len()));
     }
 
-    #[test]
-    #[should_panic]
-    fn example_5_04_invalid_end_err() {
-        let file_name: PathBuf = "logo_examples/5_04_invalid_end_err.lg".into();
-        let image_path: PathBuf = "result.svg".into();
-        let _ = start(file_name.to_path_buf(), image_path.clone(), WIDTH, HEIGHT);
+    fn flood_fill(&mut self, (x, y): (f32, f32), _: Color) {
+        self.calls.push(format!("flood_fill {x} {y}"));
     }
 
-    #[test]
-    #[should_panic]
-    fn invalid_var_name() {
-        let file_name: PathBuf = "tests/invalid_var_name.lg".into();
-        let image_path: PathBuf = "result.svg".into();
-        let _ = start(file_name.to_path_buf(), image_path.clone(), WIDTH, HEIGHT);
+    fn finish(&mut self, _: &Path) -> Result<(), String> {
+        Ok(())
     }
+}
 
-    #[test]
-    #[should_panic]
-    fn divide_by_0() {
-        let file_name: PathBuf = "tests/divide_by_0.lg".into();
-        let image_path: PathBuf = "result.svg".into();
-        let _ = start(file_name.to_path_buf(), image_path.clone(), WIDTH, HEIGHT);
+#[test]
+fn renderer_calls() {
+    let file_name: PathBuf = "tests/renderer_calls.lg".into();
+    let mut renderer = RecordingRenderer::default();
+    let _ = run(&file_name, &mut renderer, COLORS[0]);
+    assert_eq!(
+        renderer.calls,
+        [
+            "set_stroke [255 255 255] 1",
+            "move_to 100 100",
+            "line_to 100 90",
+            "move_to 100 85",
+            "set_stroke [255 255 255] 1",
+            "arc 100 85 0 360 3",
+        ]
+    );
+}
+
+#[test]
+fn display_list_geometry() {
+    let file_name: PathBuf = "tests/display_list.lg".into();
+    let mut display_list = DisplayList::new(WIDTH, HEIGHT);
+    let _ = run(&file_name, &mut display_list, COLORS[0]);
+    let lines: Vec<_> = display_list
+        .items
+        .iter()
+        .map(|item| match &item.primitive {
+            Primitive::Line {
+                from, to, color, ..
+            } => (item.line, *from, *to, color.green),
+            primitive => panic!("Unexpected primitive {primitive:?}"),
+        })
+        .collect();
+    assert_eq!(
+        lines,
+        [
+            (2, (100.0, 100.0), (100.0, 90.0), 255),
+            (7, (100.0, 90.0), (100.0, 95.0), 0),
+        ]
+    );
+}
+
+#[test]
+fn json_output() {
+    let file_name: PathBuf = "tests/json_output.lg".into();
+    let image_path: PathBuf = "json_result.json".into();
+    let _ = start(file_name, image_path.clone(), WIDTH, HEIGHT);
+    let out = fs::read_to_string(image_path).unwrap();
+    let _ = fs::remove_file("json_result.json");
+    assert_eq!(out, fs::read_to_string("tests/json_output.json").unwrap());
+}
+
+#[test]
+fn gcode_output() {
+    let file_name: PathBuf = "tests/gcode_output.lg".into();
+    let image_path: PathBuf = "gcode_result.gcode".into();
+    let _ = start(file_name, image_path.clone(), WIDTH, HEIGHT);
+    let out = fs::read_to_string(image_path).unwrap();
+    let _ = fs::remove_file("gcode_result.gcode");
+    assert_eq!(out, fs::read_to_string("tests/gcode_output.gcode").unwrap());
+}
+
+#[test]
+fn hpgl_output() {
+    let file_name: PathBuf = "tests/hpgl_output.lg".into();
+    let image_path: PathBuf = "hpgl_result.hpgl".into();
+    let _ = start(file_name, image_path.clone(), WIDTH, HEIGHT);
+    let out = fs::read_to_string(image_path).unwrap();
+    let _ = fs::remove_file("hpgl_result.hpgl");
+    assert_eq!(out, fs::read_to_string("tests/hpgl_output.hpgl").unwrap());
+}
+
+#[test]
+fn optimize_paths_output() {
+    let file_name: PathBuf = "tests/optimize_paths.lg".into();
+    let mut display_list = DisplayList::new(WIDTH, HEIGHT);
+    let _ = run(&file_name, &mut display_list, COLORS[0]);
+    let report = optimize_paths(&mut display_list);
+    assert_eq!(
+        format!("{report}"),
+        "Pen-up travel reduced from 140.6 to 97.8 pixels"
+    );
+    let lines: Vec<_> = display_list
+        .items
+        .iter()
+        .map(|item| match &item.primitive {
+            Primitive::Line { from, to, .. } => (*from, *to),
+            primitive => panic!("Unexpected primitive {primitive:?}"),
+        })
+        .collect();
+    assert_eq!(
+        lines,
+        [
+            ((100.0, 100.0), (100.0, 80.0)),
+            ((120.0, 95.0), (120.0, 100.0)),
+            ((120.0, 100.0), (140.0, 100.0)),
+            ((120.0, 30.0), (100.0, 30.0)),
+        ]
+    );
+}
+
+#[test]
+fn compact_svg() {
+    let file_name: PathBuf = "tests/compact_svg.lg".into();
+    let image_path: PathBuf = "compact_result.svg".into();
+    let options = OutputOptions {
+        svg: SvgOptions {
+            compact: true,
+            color_layers: true,
+            precision: 1,
+        },
+        ..OutputOptions::default()
+    };
+    let _ = start_with_options(file_name, image_path.clone(), WIDTH, HEIGHT, options);
+    let out = fs::read_to_string(image_path).unwrap();
+    let _ = fs::remove_file("compact_result.svg");
+    assert_eq!(out, fs::read_to_string("tests/compact_svg.svg").unwrap());
+}
+
+#[test]
+fn animated_svg() {
+    let file_name: PathBuf = "tests/animated_svg.lg".into();
+    let image_path: PathBuf = "animated_result.svg".into();
+    let options = OutputOptions {
+        animation: Some(Animation {
+            frames_per_step: 0.5,
+            duration: 4.0,
+        }),
+        ..OutputOptions::default()
+    };
+    let _ = start_with_options(file_name, image_path.clone(), WIDTH, HEIGHT, options);
+    let out = fs::read_to_string(image_path).unwrap();
+    let _ = fs::remove_file("animated_result.svg");
+    assert_eq!(out, fs::read_to_string("tests/animated_svg.svg").unwrap());
+}
+
+#[test]
+fn animated_gif_frames() {
+    let file_name: PathBuf = "tests/animated_svg.lg".into();
+    let image_path: PathBuf = "animated_result.gif".into();
+    let _ = start(file_name, image_path.clone(), WIDTH, HEIGHT);
+    let file = fs::File::open(&image_path).unwrap();
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::RGBA);
+    let mut decoder = options.read_info(file).unwrap();
+    let mut frames = 0;
+    while let Some(frame) = decoder.read_next_frame().unwrap() {
+        assert_eq!(frame.delay, 125);
+        frames += 1;
     }
+    let _ = fs::remove_file("animated_result.gif");
+    assert_eq!(frames, 4);
+}
+
+#[test]
+fn preview_output() {
+    let file_name: PathBuf = "tests/preview.lg".into();
+    let mut image = Canvas::new(WIDTH, HEIGHT);
+    image.set_antialias(false);
+    let _ = run(&file_name, &mut image, COLORS[0]);
+    let options = PreviewOptions {
+        style: PreviewStyle::Braille,
+        columns: 25,
+    };
+    let out = preview(&image.to_pixmap().unwrap(), COLORS[0], &options);
+    assert_eq!(out, fs::read_to_string("tests/preview.txt").unwrap());
+}
+
+#[test]
+fn label_fonts_use_bundled_font_in_png() {
+    let label = |family: &str| {
+        let mut image = Canvas::new(WIDTH, HEIGHT);
+        let font = Font {
+            family: family.to_string(),
+            ..Font::default()
+        };
+        image.draw_text((20.0, 100.0), 90, "Hello", &font, COLORS[7]);
+        image.to_pixmap().unwrap()
+    };
+    let bundled = label("sans-serif");
+    assert!(bundled.pixels().iter().any(|p| p.red() > 0));
+    assert_eq!(bundled, label("Courier New"));
+    assert_eq!(bundled, label("Not A Real Font"));
+}
+
+#[test]
+fn svg_precision_limit() {
+    let args = |precision: &str| {
+        Args::try_parse_from([
+            "rslogo",
+            "in.lg",
+            "out.svg",
+            "200",
+            "200",
+            "--svg-precision",
+            precision,
+        ])
+    };
+    assert!(args("6").is_ok());
+    assert!(args("7").is_err());
+    assert_eq!(format_number(1.0 / 3.0, MAX_PRECISION), "0.333333");
+    assert_eq!(format_number(1.0 / 3.0, 50), "0.333333");
+}
+
+#[test]
+fn pdf_output() {
+    let file_name: PathBuf = "tests/vector_output.lg".into();
+    let image_path: PathBuf = "pdf_result.pdf".into();
+    let _ = start(file_name, image_path.clone(), WIDTH, HEIGHT);
+    let out = fs::read_to_string(image_path).unwrap();
+    let _ = fs::remove_file("pdf_result.pdf");
+    assert_eq!(out, fs::read_to_string("tests/vector_output.pdf").unwrap());
+}
+
+#[test]
+fn eps_output() {
+    let file_name: PathBuf = "tests/vector_output.lg".into();
+    let image_path: PathBuf = "eps_result.eps".into();
+    let _ = start(file_name, image_path.clone(), WIDTH, HEIGHT);
+    let out = fs::read_to_string(image_path).unwrap();
+    let _ = fs::remove_file("eps_result.eps");
+    assert_eq!(out, fs::read_to_string("tests/vector_output.eps").unwrap());
 }
@@ -0,0 +1,113 @@
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum YAxis {
+    Up,
+    Down,
+}
+
+impl FromStr for YAxis {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "UP" => Ok(YAxis::Up),
+            "DOWN" => Ok(YAxis::Down),
+            _ => Err(format!(
+                "Error: Unknown y axis direction '{s}'. Expected up or down"
+            )),
+        }
+    }
+}
+
+/// Maps the user-space coordinates turtles move in onto canvas pixels.
+///
+/// The default transform is the identity, so user space is the pixel grid
+/// with the origin in the top left corner and the Y axis pointing down. In
+/// it, turtles move between whole pixels as they always have; any other
+/// transform keeps their positions exact, so fractional units can be drawn.
+///
+/// Only geometry is transformed: positions, lengths, radii and shapes. Pen
+/// sizes, dash patterns and label heights are always in pixels, so the
+/// default pen draws the same one pixel line whatever the scale.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    /// Pixel position of the user-space origin.
+    pub origin: (f32, f32),
+    /// Pixels per user unit.
+    pub scale: f32,
+    pub y_axis: YAxis,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform {
+            origin: (0.0, 0.0),
+            scale: 1.0,
+            y_axis: YAxis::Down,
+        }
+    }
+}
+
+impl Transform {
+    fn y_sign(self) -> f32 {
+        match self.y_axis {
+            YAxis::Up => -1.0,
+            YAxis::Down => 1.0,
+        }
+    }
+
+    pub fn to_canvas(self, (x, y): (f32, f32)) -> (f32, f32) {
+        (
+            self.origin.0 + x * self.scale,
+            self.origin.1 + y * self.scale * self.y_sign(),
+        )
+    }
+
+    pub fn to_user(self, (x, y): (f32, f32)) -> (f32, f32) {
+        self.snap((
+            (x - self.origin.0) / self.scale,
+            (y - self.origin.1) / (self.scale * self.y_sign()),
+        ))
+    }
+
+    /// Rounds a user-space point onto the pixel grid if this is the default
+    /// transform, and leaves it exact otherwise.
+    pub fn snap(self, (x, y): (f32, f32)) -> (f32, f32) {
+        if self == Transform::default() {
+            (x.round(), y.round())
+        } else {
+            (x, y)
+        }
+    }
+
+    /// Where a move from (x, y) ends in user space. Headings are always
+    /// measured clockwise from the top of the canvas.
+    pub fn end_coordinates(self, (x, y): (f32, f32), heading: i32, length: f32) -> (f32, f32) {
+        let direction = (heading.rem_euclid(360) as f32 - 90.0).to_radians();
+        let end_x = x + direction.cos() * length;
+        let end_y = y + direction.sin() * length * self.y_sign();
+        if self == Transform::default() {
+            // unsvg rounds to 1/256 of a pixel before rounding to a whole
+            // pixel, and drawings in pixels must end up exactly where they
+            // always have.
+            let quantize = |v: f32| (v * 256.0).round() / 256.0;
+            self.snap((quantize(end_x), quantize(end_y)))
+        } else {
+            (end_x, end_y)
+        }
+    }
+
+    /// The heading that points from one user-space point towards another.
+    pub fn heading_between(self, from: (f32, f32), to: (f32, f32)) -> i32 {
+        let dx = to.0 - from.0;
+        let dy = (to.1 - from.1) * self.y_sign();
+        // Headings start at 0 degrees pointing up and increase clockwise.
+        let heading = dx.atan2(-dy).to_degrees().round() as i32;
+        heading.rem_euclid(360)
+    }
+
+    pub fn to_canvas_length(self, length: f32) -> f32 {
+        length * self.scale
+    }
+}
@@ -1,21 +1,218 @@
-use std::{collections::VecDeque, path::PathBuf};
+use std::{
+    collections::VecDeque,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
-use unsvg::{Color, Image, COLORS};
+use unsvg::{Color, COLORS};
 
-use crate::{execute::execute_commands, parse::parse_commands};
+use crate::{
+    canvas::{Animation, Canvas, Font, Framing, StrokeStyle, SvgOptions},
+    display::DisplayList,
+    execute::{draw_turtles, execute_commands},
+    gcode::{Gcode, GcodeOptions},
+    hpgl::{Hpgl, HpglOptions},
+    json::save_json,
+    lsystem::{default_actions, Action},
+    optimize::optimize_paths,
+    page::{Page, PageFormat},
+    parse::parse_commands,
+    preview::{preview, PreviewOptions},
+    render::{PenPlotter, Renderer},
+    shape::Shape,
+    transform::Transform,
+};
 
 pub const VALUE_PREFIXES: [&str; 2] = ["\"", ":"];
-pub const QUERIES: [&str; 4] = ["XCOR", "YCOR", "HEADING", "COLOR"];
+pub const QUERIES: [&str; 10] = [
+    "XCOR",
+    "YCOR",
+    "HEADING",
+    "COLOR",
+    "POS",
+    "PENDOWNP",
+    "PENCOLOR",
+    "WHO",
+    "SHOWNP",
+    "BACKGROUND",
+];
+pub const DEFAULT_TURTLE: &str = "0";
+pub const OPERATORS: [&str; 12] = [
+    "EQ", "NE", "GT", "LT", "AND", "OR", "+", "-", "*", "/", "TOWARDS", "DISTANCE",
+];
 
+/// Interpreter state shared by every turtle.
+#[derive(Debug)]
+pub struct Environment {
+    pub(crate) turtles: Vec<Turtle>,
+    pub(crate) active: usize,
+    pub(crate) palette: Vec<Color>,
+    /// The color of the canvas behind everything drawn.
+    pub(crate) background: Color,
+    pub(crate) variables: Vec<Variable>,
+    pub(crate) procedures: Vec<Procedure>,
+    pub(crate) lsystem_actions: Vec<(char, Action)>,
+    pub(crate) transform: Transform,
+    /// Whether `SETSHAPE` is recording a shape procedure.
+    pub(crate) recording_shape: bool,
+    /// The source line of the command being executed.
+    pub(crate) line: usize,
+}
+
+impl Environment {
+    pub fn new(turtle: Turtle) -> Environment {
+        Environment {
+            turtles: vec![turtle],
+            active: 0,
+            palette: COLORS.to_vec(),
+            background: COLORS[0],
+            variables: Vec::new(),
+            procedures: Vec::new(),
+            lsystem_actions: default_actions(),
+            transform: Transform::default(),
+            recording_shape: false,
+            line: 0,
+        }
+    }
+
+    /// Switches to a new coordinate system, keeping every turtle at the same
+    /// place on the canvas.
+    pub fn set_transform(&mut self, transform: Transform) {
+        // Shapes are recorded in a temporary coordinate system, which is
+        // swapped back out when the recording ends.
+        if self.recording_shape {
+            exit_with_error(
+                "Error: A shape procedure can't change the coordinate system".to_string(),
+            );
+            return;
+        }
+        let old = self.transform;
+        let convert = |x: &mut f32, y: &mut f32| {
+            (*x, *y) = transform.to_user(old.to_canvas((*x, *y)));
+        };
+        for turtle in self.turtles.iter_mut() {
+            convert(&mut turtle.pos_x, &mut turtle.pos_y);
+            for state in turtle.saved_states.iter_mut() {
+                convert(&mut state.pos_x, &mut state.pos_y);
+            }
+        }
+        self.transform = transform;
+    }
+
+    /// The turtle that commands are currently sent to.
+    pub fn turtle(&self) -> &Turtle {
+        &self.turtles[self.active]
+    }
+
+    pub fn turtle_mut(&mut self) -> &mut Turtle {
+        &mut self.turtles[self.active]
+    }
+
+    /// The variable `name` refers to. A procedure's arguments are pushed
+    /// after its caller's variables and dropped when it returns, so the most
+    /// recent binding wins: arguments shadow variables of the same name, and
+    /// each recursive call sees its own arguments.
+    pub fn variable(&self, name: &str) -> Option<&Variable> {
+        self.variables.iter().rev().find(|v| v.name == name)
+    }
+
+    /// The variable `name` refers to, as in [`Environment::variable`]. `MAKE`
+    /// inside a procedure therefore rebinds a shadowing argument rather than
+    /// the variable it hides.
+    pub fn variable_mut(&mut self, name: &str) -> Option<&mut Variable> {
+        self.variables.iter_mut().rev().find(|v| v.name == name)
+    }
+
+    pub fn find_turtle(&self, name: &str) -> Option<usize> {
+        self.turtles.iter().position(|turtle| turtle.name == name)
+    }
+}
+
+/// The drawing cursor and pen state of a single turtle.
 #[derive(Debug)]
 pub struct Turtle {
+    pub(crate) name: String,
     pub(crate) pen_down: bool,
+    /// Whether the turtle is drawn where it finishes.
+    pub(crate) shown: bool,
+    /// What `STAMP` draws, and how the turtle is drawn when shown.
+    pub(crate) shape: Shape,
     pub(crate) color: Color,
+    pub(crate) stroke: StrokeStyle,
+    pub(crate) fill_color: Color,
+    pub(crate) fill: Option<FillRecording>,
+    pub(crate) label_font: Font,
     pub(crate) heading: i32,
-    pub(crate) pos_x: i32,
-    pub(crate) pos_y: i32,
-    pub(crate) variables: Vec<Variable>,
-    pub(crate) procedures: Vec<Procedure>,
+    /// The turtle's position in user space.
+    pub(crate) pos_x: f32,
+    pub(crate) pos_y: f32,
+    pub(crate) saved_states: Vec<TurtleState>,
+}
+
+/// A snapshot of a turtle saved by `PUSHTURTLE` and restored by `POPTURTLE`.
+#[derive(Debug, Clone)]
+pub struct TurtleState {
+    pub(crate) pos_x: f32,
+    pub(crate) pos_y: f32,
+    pub(crate) heading: i32,
+    pub(crate) pen_down: bool,
+    pub(crate) color: Color,
+    pub(crate) stroke: StrokeStyle,
+}
+
+impl Turtle {
+    pub fn new(name: String, pos_x: f32, pos_y: f32) -> Turtle {
+        Turtle {
+            name,
+            pen_down: false,
+            shown: false,
+            shape: Shape::default(),
+            color: COLORS[7],
+            stroke: StrokeStyle::default(),
+            fill_color: COLORS[7],
+            fill: None,
+            label_font: Font::default(),
+            heading: 0,
+            pos_x,
+            pos_y,
+            saved_states: Vec::new(),
+        }
+    }
+
+    pub fn push_state(&mut self) {
+        self.saved_states.push(TurtleState {
+            pos_x: self.pos_x,
+            pos_y: self.pos_y,
+            heading: self.heading,
+            pen_down: self.pen_down,
+            color: self.color,
+            stroke: self.stroke.clone(),
+        });
+    }
+
+    /// Restores the most recently pushed state, returning false if there is none.
+    pub fn pop_state(&mut self) -> bool {
+        match self.saved_states.pop() {
+            Some(state) => {
+                self.pos_x = state.pos_x;
+                self.pos_y = state.pos_y;
+                self.heading = state.heading;
+                self.pen_down = state.pen_down;
+                self.color = state.color;
+                self.stroke = state.stroke;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// The turtle path captured between `BEGINFILL` and `ENDFILL`, in canvas
+/// coordinates.
+#[derive(Debug, Clone)]
+pub struct FillRecording {
+    pub(crate) layer: usize,
+    pub(crate) points: Vec<(f32, f32)>,
 }
 
 #[derive(Debug, Clone)]
@@ -28,27 +225,65 @@ pub struct Variable {
 pub struct Procedure {
     pub(crate) name: String,
     pub(crate) args: Vec<String>,
-    pub(crate) commands: Vec<Command>,
+    pub(crate) commands: Vec<Statement>,
+}
+
+/// A command and the line of the program it was parsed from.
+#[derive(Debug, Clone)]
+pub struct Statement {
+    pub line: usize,
+    pub command: Command,
 }
 
 #[derive(Debug, Clone)]
 pub enum Command {
     PenUp,
     PenDown,
+    ShowTurtle,
+    HideTurtle,
+    Stamp,
+    SetShape(Expression),
+    SetBackground(Expression),
     Forward(Expression),
     Back(Expression),
     Left(Expression),
     Right(Expression),
     SetPenColor(Expression),
+    SetPalette((Expression, Expression)),
+    SetPenSize(Expression),
+    SetLineCap(Expression),
+    SetDash(Expression),
+    SetPenAlpha(Expression),
+    SetPenGradient((Expression, Expression)),
+    ClearPenGradient,
+    SetFillColor(Expression),
+    BeginFill,
+    EndFill,
+    Fill,
     Turn(Expression),
+    Arc((Expression, Expression)),
+    Circle(Expression),
+    Label(Expression),
+    SetLabelFont(Expression),
+    SetLabelHeight(Expression),
     SetHeading(Expression),
+    SetScale(Expression),
+    SetOrigin((Expression, Expression)),
+    SetYAxis(Expression),
     SetX(Expression),
     SetY(Expression),
     Make((Expression, Expression)),
     AddAssign((Expression, Expression)),
-    If((Expression, Vec<Command>)),
-    While((Expression, Vec<Command>)),
-    To((String, Vec<Expression>, Vec<Command>)),
+    If((Expression, Vec<Statement>)),
+    While((Expression, Vec<Statement>)),
+    LSystem((Expression, Expression, Expression, Expression, Expression)),
+    LSystemAction((Expression, Expression)),
+    PushTurtle,
+    PopTurtle,
+    NewTurtle(Expression),
+    Tell(Expression),
+    Ask((Expression, Vec<Statement>)),
+    To((String, Vec<Expression>, Vec<Statement>)),
     Procedure((String, Vec<Expression>)),
 }
 
@@ -64,6 +299,9 @@ pub enum Expression {
     Subtract([Box<Expression>; 2]),
     Multiply([Box<Expression>; 2]),
     Divide([Box<Expression>; 2]),
+    Towards([Box<Expression>; 2]),
+    Distance([Box<Expression>; 2]),
+    List(Vec<Expression>),
     Value(String),
 }
 
@@ -121,7 +359,45 @@ impl Expression {
                     let right = Expression::from_tokens(tokens)?;
                     Some(Expression::Divide([Box::new(left), Box::new(right)]))
                 }
-                "[" => Some(Expression::Value("[".to_string())),
+                "TOWARDS" => {
+                    let x = Expression::from_tokens(tokens)?;
+                    let y = Expression::from_tokens(tokens)?;
+                    Some(Expression::Towards([Box::new(x), Box::new(y)]))
+                }
+                "DISTANCE" => {
+                    let x = Expression::from_tokens(tokens)?;
+                    let y = Expression::from_tokens(tokens)?;
+                    Some(Expression::Distance([Box::new(x), Box::new(y)]))
+                }
+                "[" => {
+                    // A trailing bracket opens a block, otherwise it starts a list.
+                    if tokens.is_empty() {
+                        return Some(Expression::Value("[".to_string()));
+                    }
+                    let mut items = Vec::new();
+                    loop {
+                        match tokens.front() {
+                            Some(&"]") => {
+                                tokens.pop_front();
+                                return Some(Expression::List(items));
+                            }
+                            // Bare words in a list are literal values.
+                            Some(&item)
+                                if !is_valid_value(item)
+                                    && !OPERATORS.contains(&item.to_uppercase().as_str())
+                                    && item != "[" =>
+                            {
+                                tokens.pop_front();
+                                items.push(Expression::Value(format!("\"{item}")));
+                            }
+                            Some(_) => items.push(Expression::from_tokens(tokens)?),
+                            None => {
+                                exit_with_error("Error: Unclosed list".to_string());
+                                return None;
+                            }
+                        }
+                    }
+                }
                 _ => {
                     if is_valid_value(token) {
                         Some(Expression::Value(token.to_string()))
@@ -158,43 +434,194 @@ pub fn is_valid_value(str: &str) -> bool {
     false
 }
 
-pub fn save_image(image_path: PathBuf, image: Image) {
-    match image_path.extension().and_then(|s| s.to_str()) {
-        Some("svg") => {
-            let res = image.save_svg(&image_path);
-            if let Err(e) = res {
-                exit_with_error(format!("Error saving svg: {e}"));
-            }
+/// Parses an evaluated list such as `[1 2 3]` into its items.
+pub fn parse_list<T: FromStr>(value: &str) -> Option<Vec<T>> {
+    value
+        .strip_prefix('[')?
+        .strip_suffix(']')?
+        .split_whitespace()
+        .map(|item| item.parse::<T>().ok())
+        .collect()
+}
+
+pub fn save_image(
+    image_path: PathBuf,
+    display_list: &DisplayList,
+    env: &Environment,
+    options: &OutputOptions,
+) {
+    // Writing to `-` only previews the drawing.
+    let preview_only = image_path.as_os_str() == "-";
+    if preview_only || options.preview.is_some() {
+        let preview_options = options.preview.unwrap_or_default();
+        if let Err(e) = print_preview(display_list, env, options, &preview_options) {
+            exit_with_error(format!("Error previewing image: {e}"));
         }
-        Some("png") => {
-            let res = image.save_png(&image_path);
-            if let Err(e) = res {
-                exit_with_error(format!("Error saving png: {e}"));
-            }
+    }
+    if preview_only {
+        return;
+    }
+    let res = match image_path.extension().and_then(|s| s.to_str()) {
+        Some("json") => {
+            save_json(&image_path, display_list, env).map_err(|e| format!("Error saving json: {e}"))
+        }
+        Some("pdf") => {
+            let (width, height) = display_list.dimensions();
+            let mut document = Page::new(PageFormat::Pdf, width, height, env.background);
+            display_list.replay(&mut document);
+            document
+                .finish(&image_path)
+                .map_err(|e| format!("Error saving pdf: {e}"))
+        }
+        Some("eps") => {
+            let (width, height) = display_list.dimensions();
+            let mut document = Page::new(PageFormat::Eps, width, height, env.background);
+            display_list.replay(&mut document);
+            document
+                .finish(&image_path)
+                .map_err(|e| format!("Error saving eps: {e}"))
+        }
+        Some("gcode") => {
+            let (width, height) = display_list.dimensions();
+            let mut plotter = PenPlotter::new(Gcode::new(width, height, options.gcode.clone()));
+            plot(display_list, options, &mut plotter);
+            plotter
+                .finish(&image_path)
+                .map_err(|e| format!("Error saving gcode: {e}"))
+        }
+        Some("hpgl" | "plt") => {
+            let (width, height) = display_list.dimensions();
+            let palette = env.palette.clone();
+            let mut plotter =
+                PenPlotter::new(Hpgl::new(width, height, palette, options.hpgl.clone()));
+            plot(display_list, options, &mut plotter);
+            plotter
+                .finish(&image_path)
+                .map_err(|e| format!("Error saving hpgl: {e}"))
         }
         _ => {
-            exit_with_error(format!("File extension not supported"));
+            let (width, height) = display_list.dimensions();
+            let mut image = Canvas::new(width, height);
+            image.set_framing(options.framing);
+            image.set_background(env.background);
+            image.set_svg_options(options.svg.clone());
+            image.set_animation(options.animation);
+            display_list.replay(&mut image);
+            image.finish(&image_path)
         }
+    };
+    if let Err(e) = res {
+        exit_with_error(e);
     }
 }
 
+/// Prints the drawing to stdout as text, framed as it would be in png output.
+fn print_preview(
+    display_list: &DisplayList,
+    env: &Environment,
+    options: &OutputOptions,
+    preview_options: &PreviewOptions,
+) -> Result<(), String> {
+    let (width, height) = display_list.dimensions();
+    let mut image = Canvas::new(width, height);
+    image.set_framing(options.framing);
+    image.set_background(env.background);
+    image.set_antialias(false);
+    display_list.replay(&mut image);
+    let pixmap = image.to_pixmap()?;
+    print!("{}", preview(&pixmap, env.background, preview_options));
+    Ok(())
+}
+
+/// Replays a drawing into a plotter backend, optimizing the pen's path first
+/// if asked to.
+fn plot(display_list: &DisplayList, options: &OutputOptions, plotter: &mut dyn Renderer) {
+    if options.optimize_paths {
+        let mut optimized = display_list.clone();
+        let report = optimize_paths(&mut optimized);
+        eprintln!("{report}");
+        optimized.replay(plotter);
+    } else {
+        display_list.replay(plotter);
+    }
+}
+
+/// Options controlling how the finished drawing is written out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputOptions {
+    pub framing: Framing,
+    pub svg: SvgOptions,
+    pub gcode: GcodeOptions,
+    pub hpgl: HpglOptions,
+    /// Reorder and join strokes to reduce pen-up travel in plotter output.
+    pub optimize_paths: bool,
+    /// Animate svg output and write png output as numbered frames. Gif output
+    /// is always animated.
+    pub animation: Option<Animation>,
+    /// Also print the drawing to the terminal. Writing to `-` only prints it.
+    pub preview: Option<PreviewOptions>,
+    /// The background the program starts with, until it sets its own.
+    pub background: Color,
+}
+
+impl Default for OutputOptions {
+    fn default() -> Self {
+        OutputOptions {
+            framing: Framing::Canvas,
+            svg: SvgOptions::default(),
+            gcode: GcodeOptions::default(),
+            hpgl: HpglOptions::default(),
+            optimize_paths: false,
+            animation: None,
+            preview: None,
+            background: COLORS[0],
+        }
+    }
+}
+
+/// Runs a program with the default output options.
+#[cfg(test)]
 pub fn start(file_path: PathBuf, image_path: PathBuf, width: u32, height: u32) -> Result<(), ()> {
-    let mut image = Image::new(width, height);
-
-    let dimensions = image.get_dimensions();
-    let (x, y) = (dimensions.0 as i32, dimensions.1 as i32);
-    let mut turtle = Turtle {
-        pen_down: false,
-        color: COLORS[7],
-        heading: 0,
-        pos_x: x / 2,
-        pos_y: y / 2,
-        variables: Vec::new(),
-        procedures: Vec::new(),
-    };
+    start_with_options(
+        file_path,
+        image_path,
+        width,
+        height,
+        OutputOptions::default(),
+    )
+}
 
-    let commands = parse_commands(&file_path)?;
-    execute_commands(&mut turtle, &commands, &mut image);
-    save_image(image_path, image);
+pub fn start_with_options(
+    file_path: PathBuf,
+    image_path: PathBuf,
+    width: u32,
+    height: u32,
+    options: OutputOptions,
+) -> Result<(), ()> {
+    let mut display_list = DisplayList::new(width, height);
+    let env = run(&file_path, &mut display_list, options.background)?;
+    save_image(image_path, &display_list, &env, &options);
     Ok(())
 }
+
+/// The pixel turtles start on, in the middle of the canvas.
+pub fn canvas_centre((width, height): (u32, u32)) -> (f32, f32) {
+    ((width / 2) as f32, (height / 2) as f32)
+}
+
+/// Parses and executes the program at `file_path`, drawing into `image` on a
+/// canvas that starts out `background`.
+pub fn run(
+    file_path: &Path,
+    image: &mut dyn Renderer,
+    background: Color,
+) -> Result<Environment, ()> {
+    let (x, y) = canvas_centre(image.dimensions());
+    let mut env = Environment::new(Turtle::new(DEFAULT_TURTLE.to_string(), x, y));
+    env.background = background;
+
+    let commands = parse_commands(file_path)?;
+    execute_commands(&mut env, &commands, image);
+    draw_turtles(&env, image);
+    Ok(env)
+}
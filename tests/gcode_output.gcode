@@ -0,0 +1,13 @@
+G21 ; millimetres
+G90 ; absolute positioning
+G0 Z5
+G0 X25 Y25
+G0 Z0
+G1 X25 Y30 F1500
+G1 X30 Y30 F1500
+G0 Z5
+G0 X30 Y32.5
+G0 Z0
+G1 X30 Y30 F1500
+G0 Z5
+M2